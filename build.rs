@@ -1,5 +1,102 @@
+use std::{env, f64::consts::PI, fmt::Write as _, fs, path::Path};
+
+#[path = "src/synth/wavetable_gen.rs"]
+mod wavetable_gen;
+
+use wavetable_gen::{harmonics, MIPMAP_LEVELS, WAVETABLE_SIZE};
+
+/// Wavetable frames have no mipmaps, harmonics are limited so notes up to ~750Hz don't alias
+const BASIC_SHAPES_HARMONICS: usize = 32;
+
 fn main() {
-    println!("cargo:rustc-link-arg-bins=--nmagic");
-    println!("cargo:rustc-link-arg-bins=-Tlink.x");
-    println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/synth/wavetable_gen.rs");
+
+    // Linker scripts of the firmware, host test builds use the default ones
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("none") {
+        println!("cargo:rustc-link-arg-bins=--nmagic");
+        println!("cargo:rustc-link-arg-bins=-Tlink.x");
+        println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+    }
+
+    gen_band_limited();
+    gen_basic_shapes();
+}
+
+/// Matches `WaveForm::Saw`, rising from -1 to 1
+fn saw(n: usize) -> f64 {
+    -2.0 / (PI * n as f64)
+}
+
+fn square(n: usize) -> f64 {
+    if n % 2 == 1 {
+        4.0 / (PI * n as f64)
+    } else {
+        0.0
+    }
+}
+
+fn triangle(n: usize) -> f64 {
+    match n % 4 {
+        1 => 8.0 / (PI * PI * (n * n) as f64),
+        3 => -8.0 / (PI * PI * (n * n) as f64),
+        _ => 0.0,
+    }
+}
+
+fn sine(n: usize) -> f64 {
+    if n == 1 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn write_out(name: &str, code: String) {
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join(name);
+    fs::write(out, code).unwrap();
+}
+
+/// Band-limited saw and square mipmaps, generated at build time so they are kept in flash
+fn gen_band_limited() {
+    let mut code = String::from("BandLimitedWaves {\n");
+    write_mipmap(&mut code, "saw", saw);
+    write_mipmap(&mut code, "square", square);
+    code.push_str("}\n");
+
+    write_out("band_limited.rs", code);
+}
+
+/// Factory frames of the wavetable oscillator
+fn gen_basic_shapes() {
+    let mut code = String::from("[\n");
+    for harmonic in [sine, triangle, saw, square] {
+        write_table(&mut code, BASIC_SHAPES_HARMONICS, harmonic);
+    }
+    code.push_str("]\n");
+
+    write_out("basic_shapes.rs", code);
+}
+
+fn write_mipmap(code: &mut String, name: &str, harmonic: fn(usize) -> f64) {
+    writeln!(code, "{}: Mipmap {{ levels: [", name).unwrap();
+    for level in 0..MIPMAP_LEVELS {
+        write_table(code, harmonics(level), harmonic);
+    }
+    code.push_str("] },\n");
+}
+
+/// Wavetable expression filled by additive synthesis, `harmonic(n)` returns sine amplitude of n-th harmonic
+fn write_table(code: &mut String, harmonics: usize, harmonic: fn(usize) -> f64) {
+    code.push_str("Wavetable { samples: [");
+    for k in 0..WAVETABLE_SIZE {
+        let sample: f64 = (1..=harmonics)
+            .map(|n| {
+                let angle = 2.0 * PI * (n * k % WAVETABLE_SIZE) as f64 / WAVETABLE_SIZE as f64;
+                harmonic(n) * angle.sin()
+            })
+            .sum();
+        write!(code, "{:?},", sample as f32).unwrap();
+    }
+    code.push_str("] },\n");
 }
@@ -1,7 +1,8 @@
 MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 1024K 
+  FLASH : ORIGIN = 0x08000000, LENGTH = 384K
+  /* Last 128K sector of the 512K flash keeps settings, see `SETTINGS_SECTOR` */
   RAM : ORIGIN = 0x20000000, LENGTH = 256K
 }
 
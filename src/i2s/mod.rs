@@ -0,0 +1,45 @@
+pub mod pack;
+
+// // use core::marker::PhantomData;
+
+// // use embassy_stm32::{gpio::Output, Peripheral};
+
+// // pub struct I2S<'a, SPI> {
+// //     marker: PhantomData<&'a SPI>,
+// // }
+
+// // pub struct WsPin<'a> {
+// //     output: Output<'a>,
+// // }
+
+// // impl<'a> stm32_i2s_v12x::WsPin for WsPin<'a> {
+// //     fn is_low(&self) -> bool {
+// //         self.output.is_set_low()
+// //     }
+
+// //     fn is_high(&self) -> bool {
+// //         self.output.is_set_high()
+// //     }
+// // }
+
+// // unsafe impl<'a, SPI: embassy_stm32::spi::Instance> stm32_i2s_v12x::I2sPeripheral for I2S<'a, SPI> {
+// //     type WsPin = WsPin<'a>;
+
+// //     const REGISTERS: *const () = SPI;
+
+// //     fn i2s_freq(&self) -> u32 {
+// //         todo!()
+// //     }
+
+// //     fn ws_pin(&self) -> &Self::WsPin {
+// //         todo!()
+// //     }
+
+// //     fn ws_pin_mut(&mut self) -> &mut Self::WsPin {
+// //         todo!()
+// //     }
+
+// //     fn rcc_reset(&mut self) {
+// //         todo!()
+// //     }
+// // }
@@ -0,0 +1,252 @@
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum DataFormat {
+    Data16Channel16,
+    /// 16-bit data in 32-bit channel frame, hardware pads the rest
+    Data16Channel32,
+    Data24Channel32,
+    #[default]
+    Data32Channel32,
+}
+
+impl DataFormat {
+    /// Count of data register writes per sample
+    pub const fn words_per_sample(&self) -> usize {
+        match self {
+            DataFormat::Data16Channel16 | DataFormat::Data16Channel32 => 1,
+            DataFormat::Data24Channel32 | DataFormat::Data32Channel32 => 2,
+        }
+    }
+
+    pub const fn bits(&self) -> u32 {
+        match self {
+            DataFormat::Data16Channel16 | DataFormat::Data16Channel32 => 16,
+            DataFormat::Data24Channel32 => 24,
+            DataFormat::Data32Channel32 => 32,
+        }
+    }
+}
+
+/// Data alignment in channel frame. Philips and MSB-justified differ only in WS timing,
+/// only LSB-justified 24-bit data is written differently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum Layout {
+    #[default]
+    Philips,
+    Msb,
+    Lsb,
+}
+
+/// Conversion between `(i32, i32)` stereo frames and 16-bit words written to SPI/I2S data register.
+/// Samples are full-scale `i32`, lower bits are truncated for narrower formats.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub struct Format {
+    pub data: DataFormat,
+    pub layout: Layout,
+}
+
+impl Format {
+    pub const fn new(data: DataFormat, layout: Layout) -> Self {
+        Self { data, layout }
+    }
+
+    pub const fn words_per_frame(&self) -> usize {
+        self.data.words_per_sample() * 2
+    }
+
+    /// Words for a single sample, only the first `DataFormat::words_per_sample` are meaningful
+    pub fn pack_sample(&self, sample: i32) -> [u16; 2] {
+        match (self.data, self.layout) {
+            (DataFormat::Data16Channel16 | DataFormat::Data16Channel32, _) => {
+                [(sample >> 16) as u16, 0]
+            }
+            // 0xAABBCC is written as 0xAABB, 0xCC00
+            (DataFormat::Data24Channel32, Layout::Philips | Layout::Msb) => {
+                [(sample >> 16) as u16, (sample as u16) & 0xff00]
+            }
+            // 0xAABBCC is written as 0x00AA, 0xBBCC
+            (DataFormat::Data24Channel32, Layout::Lsb) => {
+                [((sample >> 24) as u16) & 0x00ff, (sample >> 8) as u16]
+            }
+            (DataFormat::Data32Channel32, _) => [(sample >> 16) as u16, sample as u16],
+        }
+    }
+
+    /// Inverse of `pack_sample`, returns full-scale sample
+    pub fn unpack_sample(&self, words: [u16; 2]) -> i32 {
+        match (self.data, self.layout) {
+            (DataFormat::Data16Channel16 | DataFormat::Data16Channel32, _) => {
+                ((words[0] as u32) << 16) as i32
+            }
+            (DataFormat::Data24Channel32, Layout::Philips | Layout::Msb) => {
+                (((words[0] as u32) << 16) | (words[1] & 0xff00) as u32) as i32
+            }
+            (DataFormat::Data24Channel32, Layout::Lsb) => {
+                ((((words[0] & 0x00ff) as u32) << 24) | ((words[1] as u32) << 8)) as i32
+            }
+            (DataFormat::Data32Channel32, _) => {
+                (((words[0] as u32) << 16) | words[1] as u32) as i32
+            }
+        }
+    }
+
+    /// Pack frames into DMA words, returns count of frames packed
+    pub fn pack(&self, frames: &[(i32, i32)], words: &mut [u16]) -> usize {
+        let sample_words = self.data.words_per_sample();
+        let mut count = 0;
+
+        for (data, frame) in words
+            .chunks_exact_mut(self.words_per_frame())
+            .zip(frames.iter())
+        {
+            let (left, right) = data.split_at_mut(sample_words);
+            left.copy_from_slice(&self.pack_sample(frame.0)[..sample_words]);
+            right.copy_from_slice(&self.pack_sample(frame.1)[..sample_words]);
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Unpack captured DMA words into frames, returns count of frames unpacked
+    pub fn unpack(&self, words: &[u16], frames: &mut [(i32, i32)]) -> usize {
+        let sample_words = self.data.words_per_sample();
+        let mut count = 0;
+
+        for (data, frame) in words
+            .chunks_exact(self.words_per_frame())
+            .zip(frames.iter_mut())
+        {
+            let (left, right) = data.split_at(sample_words);
+            let sample =
+                |words: &[u16]| self.unpack_sample([words[0], words.get(1).copied().unwrap_or(0)]);
+
+            *frame = (sample(left), sample(right));
+            count += 1;
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [DataFormat; 4] = [
+        DataFormat::Data16Channel16,
+        DataFormat::Data16Channel32,
+        DataFormat::Data24Channel32,
+        DataFormat::Data32Channel32,
+    ];
+    const LAYOUTS: [Layout; 3] = [Layout::Philips, Layout::Msb, Layout::Lsb];
+
+    const FRAMES: [(i32, i32); 6] = [
+        (0, 0),
+        (i32::MAX, i32::MIN),
+        (-1, 1),
+        (0x12345678, -0x12345678),
+        (0x7f00ff00, -0x00010000),
+        (-0x40000000, 0x40000000),
+    ];
+
+    /// Keep only bits representable in the format
+    fn truncate(sample: i32, data: DataFormat) -> i32 {
+        let shift = 32 - data.bits();
+        if shift == 0 {
+            sample
+        } else {
+            (sample >> shift) << shift
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for data in FORMATS {
+            for layout in LAYOUTS {
+                let format = Format::new(data, layout);
+                let mut words = [0; FRAMES.len() * 4];
+                let mut frames = [(0, 0); FRAMES.len()];
+
+                assert_eq!(format.pack(&FRAMES, &mut words), FRAMES.len());
+                assert_eq!(
+                    format.unpack(
+                        &words[..FRAMES.len() * format.words_per_frame()],
+                        &mut frames
+                    ),
+                    FRAMES.len()
+                );
+
+                for (unpacked, frame) in frames.iter().zip(FRAMES.iter()) {
+                    assert_eq!(
+                        *unpacked,
+                        (truncate(frame.0, data), truncate(frame.1, data)),
+                        "{:?} {:?}",
+                        data,
+                        layout
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn word_layouts() {
+        let sample = 0x12345678;
+        let pack = |data, layout| Format::new(data, layout).pack_sample(sample);
+
+        assert_eq!(
+            pack(DataFormat::Data16Channel16, Layout::Philips)[0],
+            0x1234
+        );
+        assert_eq!(pack(DataFormat::Data16Channel32, Layout::Lsb)[0], 0x1234);
+        assert_eq!(
+            pack(DataFormat::Data24Channel32, Layout::Philips),
+            [0x1234, 0x5600]
+        );
+        assert_eq!(
+            pack(DataFormat::Data24Channel32, Layout::Msb),
+            [0x1234, 0x5600]
+        );
+        assert_eq!(
+            pack(DataFormat::Data24Channel32, Layout::Lsb),
+            [0x0012, 0x3456]
+        );
+        assert_eq!(
+            pack(DataFormat::Data32Channel32, Layout::Philips),
+            [0x1234, 0x5678]
+        );
+    }
+
+    #[test]
+    fn interleaves_channels() {
+        let format = Format::new(DataFormat::Data32Channel32, Layout::Philips);
+        let mut words = [0; 8];
+        format.pack(
+            &[(0x11112222, 0x33334444), (0x55556666, 0x77778888)],
+            &mut words,
+        );
+
+        assert_eq!(
+            words,
+            [0x1111, 0x2222, 0x3333, 0x4444, 0x5555, 0x6666, 0x7777, 0x8888]
+        );
+
+        let format = Format::new(DataFormat::Data16Channel16, Layout::Philips);
+        let mut words = [0; 4];
+        format.pack(
+            &[(0x11112222, 0x33334444), (0x55556666, 0x77778888)],
+            &mut words,
+        );
+
+        assert_eq!(words, [0x1111, 0x3333, 0x5555, 0x7777]);
+    }
+
+    #[test]
+    fn packs_only_whole_frames() {
+        let format = Format::new(DataFormat::Data32Channel32, Layout::Philips);
+        let mut words = [0; 6];
+
+        assert_eq!(format.pack(&FRAMES, &mut words), 1);
+        assert_eq!(format.pack(&FRAMES[..0], &mut words), 0);
+    }
+}
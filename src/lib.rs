@@ -1,15 +1,20 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 pub mod sound;
 
 pub mod control;
+#[cfg(target_os = "none")]
 pub mod display_dma;
+#[cfg(target_os = "none")]
 pub mod heap;
 pub mod i2s;
 pub mod midi;
+pub mod settings;
+pub mod spsc;
 pub mod synth;
 pub mod ui;
+#[cfg(target_os = "none")]
 pub mod drivers;
 pub mod iter;
 
@@ -19,10 +24,13 @@ extern crate alloc;
 use core::{cell::RefCell, sync::atomic::AtomicU32};
 
 use cortex_m::interrupt::Mutex;
+#[cfg(target_os = "none")]
 use cortex_m_semihosting::debug;
+#[cfg(target_os = "none")]
 use display_dma::DisplayI2cDma;
 // use panic_halt as _;
 // use panic_semihosting as _;
+#[cfg(target_os = "none")]
 use panic_probe as _;
 use stm32_i2s_v12x::{
     marker::{Data32Channel32, Master, Philips, Transmit},
@@ -31,18 +39,21 @@ use stm32_i2s_v12x::{
 use stm32f4xx_hal::{i2s::I2s3, otg_fs::{UsbBus, USB}};
 use usb_device::bus::UsbBusAllocator;
 
+#[cfg(target_os = "none")]
 #[inline(never)]
 #[defmt::panic_handler]
 fn panic() -> ! {
     cortex_m::asm::udf()
 }
 
+#[cfg(target_os = "none")]
 pub fn exit() -> ! {
     loop {
         debug::exit(debug::EXIT_SUCCESS);
     }
 }
 
+#[cfg(target_os = "none")]
 #[cortex_m_rt::exception]
 unsafe fn HardFault(_frame: &cortex_m_rt::ExceptionFrame) -> ! {
     loop {
@@ -50,6 +61,19 @@ unsafe fn HardFault(_frame: &cortex_m_rt::ExceptionFrame) -> ! {
     }
 }
 
+/// Host tests have no RTT, defmt logs are dropped
+#[cfg(test)]
+#[defmt::global_logger]
+struct TestLogger;
+
+#[cfg(test)]
+unsafe impl defmt::Logger for TestLogger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
 pub mod board_info {
     use embedded_graphics::geometry::Size;
 
@@ -59,10 +83,12 @@ pub mod board_info {
 pub type Global<T> = Mutex<RefCell<Option<T>>>;
 
 pub const SAMPLE_RATE: u32 = 48_000;
+/// Count of stereo frames in a single DMA buffer, rendered by the synth in one block
 pub const AUDIO_BUFFER_SIZE: usize = 256;
 pub const DMA_AUDIO_BUFFER_SIZE: usize = AUDIO_BUFFER_SIZE * 2 * 2;
 pub type DmaAudioBuffer = [u16; DMA_AUDIO_BUFFER_SIZE];
 pub type MainI2s = I2sTransfer<I2s3, Master, Transmit, Philips, Data32Channel32>;
+#[cfg(target_os = "none")]
 pub type Display = ssd1306::Ssd1306<
     DisplayI2cDma,
     ssd1306::prelude::DisplaySize128x32,
@@ -72,9 +98,6 @@ pub static ELAPSED_US: AtomicU32 = AtomicU32::new(0);
 pub static ELAPSED_MS: AtomicU32 = AtomicU32::new(0);
 // pub static USB_BUS: Global<UsbBusAllocator<UsbBus<USB>>> = Mutex::new(RefCell::new(None));
 
-pub static AUDIO_BUFFER: Mutex<RefCell<heapless::Deque<(i32, i32), AUDIO_BUFFER_SIZE>>> =
-    Mutex::new(RefCell::new(heapless::Deque::new()));
-
 pub fn micros() -> u32 {
     ELAPSED_US.load(core::sync::atomic::Ordering::Relaxed)
 }
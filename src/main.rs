@@ -9,7 +9,7 @@ extern crate paw_one;
 use core::{cell::RefCell, sync::atomic::AtomicUsize};
 
 use alloc::{format, string::ToString, vec::Vec};
-use cortex_m::interrupt::Mutex;
+use cortex_m::{interrupt::Mutex, peripheral::DWT};
 use defmt::*;
 use embedded_graphics::{
     draw_target::DrawTarget,
@@ -31,20 +31,36 @@ use micromath::F32Ext;
 use paw_one::{
     control::{
         btn::{Btn, PullUp},
+        enc::EncState,
         qei_enc::QeiEnc,
         ControlPanel, ControlsState,
     },
     display_dma::{DisplayI2cDma, DISPLAY_I2C},
     drivers::ttp229::{Keys, TTP229},
     heap::init_global_heap,
+    i2s::pack::{DataFormat, Format, Layout},
     iter::digits::Digits,
     micros,
-    midi::{note::Note, UsbMidi},
+    midi::{
+        dispatcher::{ChannelFilter, Dispatcher, Event as MidiEvent, Packet},
+        note::Note,
+        param::{ParamChange, ParamParser},
+        UsbMidi,
+    },
     millis,
-    synth::Synth,
-    ui::{fps::FPS, logo::LOGO, Message},
-    DmaAudioBuffer, Global, AUDIO_BUFFER, AUDIO_BUFFER_SIZE, DMA_AUDIO_BUFFER_SIZE, ELAPSED_MS,
-    ELAPSED_US, SAMPLE_RATE,
+    settings::Settings,
+    spsc::{Consumer, Producer, Queue},
+    synth::{
+        cc_map::SynthParam, preset::PresetChange, velocity::MAX_VELOCITY, Synth, VOICES_COUNT,
+    },
+    ui::{
+        fps::FPS,
+        logo::LOGO,
+        settings::{SettingAction, SettingsPage},
+        Message,
+    },
+    DmaAudioBuffer, Global, AUDIO_BUFFER_SIZE, DMA_AUDIO_BUFFER_SIZE, ELAPSED_MS, ELAPSED_US,
+    SAMPLE_RATE,
 };
 use ssd1306::{mode::DisplayConfig as _, prelude::Brightness};
 use stm32_i2s_v12x::{
@@ -55,9 +71,10 @@ use stm32f4xx_hal::{
     dma::{
         config::DmaConfig, DmaFlag, MemoryToPeripheral, Stream4, Stream5, StreamsTuple, Transfer,
     },
+    flash::FlashExt,
     i2s::{I2s2, I2s3},
     otg_fs::{UsbBus, UsbBusType, USB},
-    pac::{DMA1, TIM12, TIM2, TIM3, TIM9},
+    pac::{DMA1, FLASH, TIM2, TIM3, TIM9},
     prelude::*,
     qei::Qei,
     timer::{CounterHz, Event, Flag},
@@ -78,10 +95,7 @@ use usb_device::{
     LangID,
 };
 use usbd_midi::{
-    data::{
-        usb::constants::{USB_AUDIO_CLASS, USB_MIDISTREAMING_SUBCLASS},
-        usb_midi::midi_packet_reader::MidiPacketBufferReader,
-    },
+    data::usb::constants::{USB_AUDIO_CLASS, USB_MIDISTREAMING_SUBCLASS},
     midi_device::MidiClass,
 };
 use {defmt_rtt as _, panic_probe as _};
@@ -89,7 +103,7 @@ use {defmt_rtt as _, panic_probe as _};
 // static I2S_TIMER: Mutex<RefCell<Option<CounterHz<TIM2>>>> = Mutex::new(RefCell::new(None));
 // static UI_TIMER: Mutex<RefCell<Option<CounterHz<TIM4>>>> = Mutex::new(RefCell::new(None));
 // static DISPLAY: Mutex<RefCell<Option<Display>>> = Mutex::new(RefCell::new(None));
-static SYNTH_TIMER: Global<CounterHz<TIM12>> = Mutex::new(RefCell::new(None));
+/// Synth handed over to the audio interrupt, which owns it after the first block
 static SYNTH: Global<Synth> = Mutex::new(RefCell::new(None));
 // static I2S: Mutex<RefCell<Option<I2sDriver<I2s<SPI3>, Master, Transmit, Philips>>>> =
 //     Mutex::new(RefCell::new(None));
@@ -99,6 +113,21 @@ static SYNTH: Global<Synth> = Mutex::new(RefCell::new(None));
 static AUDIO_BUFFER_UNDERRUN_COUNT: AtomicUsize = AtomicUsize::new(0);
 static COMMON_TIMER: Global<CounterHz<TIM2>> = Mutex::new(RefCell::new(None));
 static USB_MIDI: Global<UsbMidi> = Mutex::new(RefCell::new(None));
+/// MIDI packets received by USB interrupt, consumed by audio interrupt
+const MIDI_QUEUE_SIZE: usize = 64;
+type MidiQueue = Queue<Packet, MIDI_QUEUE_SIZE>;
+type MidiProducer = Producer<'static, Packet, MIDI_QUEUE_SIZE>;
+type MidiConsumer = Consumer<'static, Packet, MIDI_QUEUE_SIZE>;
+static MIDI_PRODUCER: Global<MidiProducer> = Mutex::new(RefCell::new(None));
+static MIDI_CONSUMER: Global<MidiConsumer> = Mutex::new(RefCell::new(None));
+/// Requests from the main loop, applied by audio interrupt before rendering the next block
+const COMMAND_QUEUE_SIZE: usize = 16;
+type CommandQueue = Queue<SynthCommand, COMMAND_QUEUE_SIZE>;
+type CommandProducer = Producer<'static, SynthCommand, COMMAND_QUEUE_SIZE>;
+type CommandConsumer = Consumer<'static, SynthCommand, COMMAND_QUEUE_SIZE>;
+static COMMAND_CONSUMER: Global<CommandConsumer> = Mutex::new(RefCell::new(None));
+/// Synth state shown by the main loop, published by audio interrupt after each block
+static SYNTH_STATUS: Global<SynthStatus> = Mutex::new(RefCell::new(None));
 // static CONTROLS_STATE: Mutex<RefCell<Option<ControlsState>>> = Mutex::new(RefCell::new(None));
 // static CONTROL_PANEL: Mutex<
 //     RefCell<
@@ -131,6 +160,99 @@ type I2sDmaTransfer = Transfer<
     &'static mut DmaAudioBuffer,
 >;
 static I2S_DMA_TRANSFER: Global<I2sDmaTransfer> = Mutex::new(RefCell::new(None));
+const I2S_FORMAT: Format = Format::new(DataFormat::Data32Channel32, Layout::Philips);
+const SYSCLK_HZ: u32 = 96_000_000;
+/// Play time of a single DMA buffer in CPU cycles
+const BLOCK_DURATION_CYCLES: u32 = AUDIO_BUFFER_SIZE as u32 * (SYSCLK_HZ / SAMPLE_RATE);
+/// NVIC priorities, lower value preempts higher. Timekeeping and USB must not wait for the audio block to render.
+const TIMER_PRIORITY: u8 = 0x00;
+const USB_PRIORITY: u8 = 0x10;
+const AUDIO_PRIORITY: u8 = 0x20;
+/// Settings live in the last 128K flash sector, excluded from the firmware in `memory.x`
+const SETTINGS_SECTOR: u8 = 7;
+const SETTINGS_OFFSET: usize = 0x6_0000;
+/// Saving gives up if output isn't silent by then, e.g. when audio doesn't run
+const SETTINGS_MUTE_TIMEOUT_MS: u32 = 500;
+/// Count of DMA buffers played in turn, both hold silence once that many silent blocks were rendered
+const DMA_BUFFERS: u8 = 2;
+
+#[derive(Clone, Copy, Debug, defmt::Format)]
+enum SynthCommand {
+    NoteOn(Note, u8),
+    NoteOff(Note),
+    StartLearn(SynthParam),
+    CancelLearn,
+    Unbind(SynthParam),
+    SetPresetChange(PresetChange),
+    SetMuted(bool),
+}
+
+impl SynthCommand {
+    fn apply(self, synth: &mut Synth) {
+        match self {
+            SynthCommand::NoteOn(note, velocity) => synth.note_on(note, velocity),
+            SynthCommand::NoteOff(note) => synth.note_off(note),
+            SynthCommand::StartLearn(param) => synth.cc_map_mut().start_learn(param),
+            SynthCommand::CancelLearn => synth.cc_map_mut().cancel_learn(),
+            SynthCommand::Unbind(param) => synth.cc_map_mut().unbind(param),
+            SynthCommand::SetPresetChange(change) => synth.set_preset_change(change),
+            SynthCommand::SetMuted(muted) => synth.master_mut().set_muted(muted),
+        }
+    }
+
+    /// Command carrying out settings page action, `None` for saving which the main loop does itself
+    fn from_setting(action: SettingAction) -> Option<Self> {
+        match action {
+            SettingAction::StartLearn(param) => Some(SynthCommand::StartLearn(param)),
+            SettingAction::CancelLearn => Some(SynthCommand::CancelLearn),
+            SettingAction::Unbind(param) => Some(SynthCommand::Unbind(param)),
+            SettingAction::SetPresetChange(change) => Some(SynthCommand::SetPresetChange(change)),
+            SettingAction::Save => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SynthStatus {
+    notes: heapless::Vec<Note, VOICES_COUNT>,
+    bank: u16,
+    program: u8,
+    preset_name: &'static str,
+    /// Device settings as currently applied to the synth
+    settings: Settings,
+    /// Highest fill level of MIDI packet queue since the main loop last showed it
+    midi_queue_high: usize,
+    /// Longest block render time in CPU cycles since the main loop last showed it
+    render_cycles_peak: u32,
+    /// Consecutive blocks rendered after muted output went silent
+    silent_blocks: u8,
+}
+
+impl SynthStatus {
+    fn new(
+        synth: &Synth,
+        midi_queue_high: usize,
+        render_cycles_peak: u32,
+        silent_blocks: u8,
+    ) -> Self {
+        Self {
+            notes: synth
+                .active_voices()
+                .filter_map(|voice| voice.current_note())
+                .collect(),
+            bank: synth.bank(),
+            program: synth.program(),
+            preset_name: synth.preset_name(),
+            settings: Settings {
+                cc_map: synth.cc_map().clone(),
+                preset_change: synth.preset_change(),
+            },
+            midi_queue_high,
+            render_cycles_peak,
+            silent_blocks,
+        }
+    }
+}
 
 // #[interrupt]
 // fn TIM2() {
@@ -202,20 +324,6 @@ fn I2C1_ER() {
     });
 }
 
-#[interrupt]
-fn TIM12() {
-    cortex_m::interrupt::free(|cs| {
-        SYNTH_TIMER
-            .borrow(cs)
-            .borrow_mut()
-            .as_mut()
-            .unwrap()
-            .clear_flags(Flag::Update);
-
-        SYNTH.borrow(cs).borrow_mut().as_mut().unwrap().tick();
-    });
-}
-
 // #[interrupt]
 // fn TIM4() {
 //     cortex_m::interrupt::free(|cs| {
@@ -239,50 +347,89 @@ fn TIM12() {
 #[interrupt]
 fn DMA1_STREAM4() {
     static mut TRANSFER: Option<I2sDmaTransfer> = None;
+    static mut OWNED_SYNTH: Option<Synth> = None;
+    static mut COMMANDS: Option<CommandConsumer> = None;
+    static mut FRAMES: [(i32, i32); AUDIO_BUFFER_SIZE] = [(0, 0); AUDIO_BUFFER_SIZE];
+    static mut MIDI_EVENTS: Option<MidiConsumer> = None;
+    static mut MIDI_DISPATCHER: Dispatcher = Dispatcher::new(ChannelFilter::Omni);
+    static mut MIDI_PARAMS: Option<ParamParser> = None;
+    static mut SILENT_BLOCKS: u8 = 0;
 
     let transfer = TRANSFER.get_or_insert_with(|| {
         cortex_m::interrupt::free(|cs| I2S_DMA_TRANSFER.borrow(cs).replace(None).unwrap())
     });
 
+    let midi_events = MIDI_EVENTS.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| MIDI_CONSUMER.borrow(cs).replace(None).unwrap())
+    });
+
+    let synth = OWNED_SYNTH.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| SYNTH.borrow(cs).replace(None).unwrap())
+    });
+
+    let commands = COMMANDS.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| COMMAND_CONSUMER.borrow(cs).replace(None).unwrap())
+    });
+
+    let midi_params = MIDI_PARAMS.get_or_insert_with(ParamParser::default);
+
     let flags = transfer.flags();
 
     transfer.clear_flags(DmaFlag::FifoError);
 
     if flags.is_transfer_complete() {
-        cortex_m::interrupt::free(|cs| {
-            unsafe {
-                transfer
-                    .next_transfer_with(|buffer, _active| {
-                        let mut pointer = 0;
-                        while let Some(frame) = AUDIO_BUFFER.borrow(cs).borrow_mut().pop_front() {
-                            let mut data = [0; 4];
-                            let left = frame.0.to_be_bytes();
-                            let right = frame.1.to_be_bytes();
-
-                            data[0..2].copy_from_slice(&[
-                                u16::from_be_bytes([left[0], left[1]]),
-                                u16::from_be_bytes([left[2], left[3]]),
-                            ]);
-
-                            data[2..4].copy_from_slice(&[
-                                u16::from_be_bytes([right[0], right[1]]),
-                                u16::from_be_bytes([right[2], right[3]]),
-                            ]);
-
-                            buffer[pointer..pointer + 4].copy_from_slice(&data);
-                            pointer += 4;
-                        }
+        let started = DWT::cycle_count();
 
-                        if pointer < buffer.len() {
-                            // defmt::panic!("Underrun");
-                            AUDIO_BUFFER_UNDERRUN_COUNT
-                                .fetch_add(1, core::sync::atomic::Ordering::Relaxed);
-                        }
+        while let Some(command) = commands.pop() {
+            command.apply(synth);
+        }
+
+        while let Some(packet) = midi_events.pop() {
+            MIDI_DISPATCHER.feed_packet(packet, |channel, event| {
+                handle_midi(synth, midi_params, channel, event)
+            });
+        }
+
+        let silent = synth.master().is_silent();
+        synth.render(FRAMES);
+        *SILENT_BLOCKS = if silent {
+            SILENT_BLOCKS.saturating_add(1)
+        } else {
+            0
+        };
+
+        unsafe {
+            transfer
+                .next_transfer_with(|buffer, _active| {
+                    I2S_FORMAT.pack(FRAMES, buffer);
+
+                    (buffer, ())
+                })
+                .unwrap();
+        }
 
-                        (buffer, ())
-                    })
-                    .unwrap();
+        // Rendering took longer than the other buffer plays, DMA has been repeating stale data
+        let render_cycles = DWT::cycle_count().wrapping_sub(started);
+        if render_cycles > BLOCK_DURATION_CYCLES {
+            AUDIO_BUFFER_UNDERRUN_COUNT.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        }
+
+        let mut status = SynthStatus::new(
+            synth,
+            midi_events.watermarks().high,
+            render_cycles,
+            *SILENT_BLOCKS,
+        );
+        midi_events.reset_watermarks();
+        cortex_m::interrupt::free(|cs| {
+            let mut published = SYNTH_STATUS.borrow(cs).borrow_mut();
+            // Keep the peak of blocks the main loop hasn't shown yet
+            if let Some(previous) = published.as_ref() {
+                status.midi_queue_high = status.midi_queue_high.max(previous.midi_queue_high);
+                status.render_cycles_peak =
+                    status.render_cycles_peak.max(previous.render_cycles_peak);
             }
+            published.replace(status);
         });
     }
 
@@ -299,6 +446,12 @@ fn DMA1_STREAM4() {
 
 #[interrupt]
 fn OTG_FS() {
+    static mut MIDI_EVENTS: Option<MidiProducer> = None;
+
+    let midi_events = MIDI_EVENTS.get_or_insert_with(|| {
+        cortex_m::interrupt::free(|cs| MIDI_PRODUCER.borrow(cs).replace(None).unwrap())
+    });
+
     cortex_m::interrupt::free(|cs| {
         USB_MIDI
             .borrow(cs)
@@ -306,41 +459,97 @@ fn OTG_FS() {
             .as_mut()
             .unwrap()
             .poll(|packet| {
-                match packet.message {
-                    usbd_midi::data::midi::message::Message::NoteOff(_, note, _) => {
-                        cortex_m::interrupt::free(|cs| {
-                            SYNTH
-                                .borrow(cs)
-                                .borrow_mut()
-                                .as_mut()
-                                .unwrap()
-                                .note_on(note.into())
-                        });
-                    }
-                    usbd_midi::data::midi::message::Message::NoteOn(_, note, _) => {
-                        cortex_m::interrupt::free(|cs| {
-                            SYNTH
-                                .borrow(cs)
-                                .borrow_mut()
-                                .as_mut()
-                                .unwrap()
-                                .note_off(note.into())
-                        });
-                    }
-                    // usbd_midi::data::midi::message::Message::PolyphonicAftertouch(_, _, _) => todo!(),
-                    // usbd_midi::data::midi::message::Message::ProgramChange(_, _) => todo!(),
-                    // usbd_midi::data::midi::message::Message::ChannelAftertouch(_, _) => todo!(),
-                    // usbd_midi::data::midi::message::Message::PitchWheelChange(_, _, _) => todo!(),
-                    // usbd_midi::data::midi::message::Message::ControlChange(_, _, _) => todo!(),
-                    _ => info!(
-                        "Unsupported message: {}",
-                        format!("{:?}", packet.message).as_str()
-                    ),
+                // Applied to the synth right before rendering the next block
+                if midi_events.push(packet).is_err() {
+                    warn!("MIDI event queue is full, dropping packet");
                 }
             });
     });
 }
 
+fn handle_midi(synth: &mut Synth, params: &mut ParamParser, channel: u8, event: MidiEvent) {
+    match event {
+        MidiEvent::NoteOn { note, velocity } => synth.note_on(note, velocity),
+        MidiEvent::NoteOff { note, .. } => synth.note_off(note),
+        MidiEvent::ChannelPressure(pressure) => synth.set_aftertouch(pressure),
+        MidiEvent::PolyPressure { note, pressure } => synth.set_poly_aftertouch(note, pressure),
+        MidiEvent::PitchBend(value) => synth.set_pitch_bend(value),
+        MidiEvent::ProgramChange(program) => synth.program_change(program),
+        MidiEvent::ControlChange { control, value } => match params.feed(channel, control, value) {
+            Some(ParamChange::Control { control, value }) => synth.control_change(control, value),
+            Some(ParamChange::Rpn { param, value }) => synth.registered_param(param, value),
+            Some(ParamChange::Nrpn { param, value }) => {
+                info!("Unsupported NRPN {} = {}", param, value)
+            }
+            None => {}
+        },
+    }
+}
+
+fn load_settings(flash: &FLASH) -> Settings {
+    Settings::from_bytes(&flash.read()[SETTINGS_OFFSET..]).unwrap_or_else(|| {
+        info!("No stored settings, using defaults");
+        Settings::default()
+    })
+}
+
+/// Erasing the sector stalls flash reads for a second or two, the audio interrupt and wavetables included,
+/// while DMA keeps repeating its buffers. Output is muted until both buffers hold silence, `true` if saved.
+fn save_settings(flash: &mut FLASH, settings: &Settings, commands: &mut CommandProducer) -> bool {
+    if commands.push(SynthCommand::SetMuted(true)).is_err() {
+        warn!("Synth command queue is full, not saving settings");
+        return false;
+    }
+
+    let started_ms = millis();
+    let silent = loop {
+        let silent_blocks = cortex_m::interrupt::free(|cs| {
+            SYNTH_STATUS
+                .borrow(cs)
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .silent_blocks
+        });
+        if silent_blocks >= DMA_BUFFERS {
+            break true;
+        }
+        if millis() - started_ms > SETTINGS_MUTE_TIMEOUT_MS {
+            break false;
+        }
+    };
+
+    let saved = if silent {
+        write_settings(flash, settings)
+    } else {
+        warn!("Output didn't go silent, not saving settings");
+        false
+    };
+
+    // Audio interrupt drains the queue every block
+    while commands.push(SynthCommand::SetMuted(false)).is_err() {}
+
+    saved
+}
+
+fn write_settings(flash: &mut FLASH, settings: &Settings) -> bool {
+    let mut flash = flash.unlocked();
+    let result = flash
+        .erase(SETTINGS_SECTOR)
+        .and_then(|_| flash.program(SETTINGS_OFFSET, settings.to_bytes().iter()));
+
+    match result {
+        Ok(()) => {
+            info!("Settings saved");
+            true
+        }
+        Err(err) => {
+            error!("Failed to save settings: {}", Debug2Format(&err));
+            false
+        }
+    }
+}
+
 // impl<
 //         'a,
 //         Message: 'a,
@@ -367,7 +576,7 @@ fn main() -> ! {
     }
 
     let dp = Peripherals::take().unwrap();
-    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
+    let mut cp = cortex_m::peripheral::Peripherals::take().unwrap();
     let syscfg = dp.SYSCFG.constrain();
 
     let gpioa = dp.GPIOA.split();
@@ -381,8 +590,8 @@ fn main() -> ! {
     let clocks = rcc
         .cfgr
         .use_hse(8.MHz())
-        .sysclk(96.MHz())
-        .hclk(96.MHz())
+        .sysclk(SYSCLK_HZ.Hz())
+        .hclk(SYSCLK_HZ.Hz())
         .i2s_apb1_clk(61440.kHz())
         // .pclk1(48.MHz())
         // .pclk2(96.MHz())
@@ -446,26 +655,31 @@ fn main() -> ! {
         ttp229
     };
 
+    let mut flash = dp.FLASH;
+    let mut saved_settings = load_settings(&flash);
+
     let mut synth = Synth::new();
+    synth.dither_mut().set_bits(I2S_FORMAT.data.bits());
+    *synth.cc_map_mut() = saved_settings.cc_map.clone();
+    synth.set_preset_change(saved_settings.preset_change);
 
     cortex_m::interrupt::free(|cs| {
+        SYNTH_STATUS
+            .borrow(cs)
+            .borrow_mut()
+            .replace(SynthStatus::new(&synth, 0, 0, 0));
         SYNTH.borrow(cs).borrow_mut().replace(synth);
     });
 
-    {
-        let mut synth_timer = dp.TIM12.counter_hz(&clocks);
-        synth_timer
-            .start((SAMPLE_RATE * 4).Hz())
-            .expect("Failed to initialize Synth timer TIM12");
-        synth_timer.listen(Event::Update);
+    // Measures audio block render time
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
 
-        cortex_m::interrupt::free(|cs| {
-            *SYNTH_TIMER.borrow(cs).borrow_mut() = Some(synth_timer);
-        });
-
-        unsafe {
-            NVIC::unmask(interrupt::TIM12);
-        }
+    unsafe {
+        cp.NVIC.set_priority(interrupt::TIM2, TIMER_PRIORITY);
+        cp.NVIC.set_priority(interrupt::OTG_FS, USB_PRIORITY);
+        cp.NVIC
+            .set_priority(interrupt::DMA1_STREAM4, AUDIO_PRIORITY);
     }
 
     {
@@ -484,6 +698,27 @@ fn main() -> ! {
         }
     }
 
+    {
+        let midi_queue = cortex_m::singleton!(: MidiQueue = MidiQueue::new()).unwrap();
+        let (producer, consumer) = midi_queue.split();
+
+        cortex_m::interrupt::free(|cs| {
+            MIDI_PRODUCER.borrow(cs).replace(Some(producer));
+            MIDI_CONSUMER.borrow(cs).replace(Some(consumer));
+        });
+    }
+
+    let mut commands: CommandProducer = {
+        let command_queue = cortex_m::singleton!(: CommandQueue = CommandQueue::new()).unwrap();
+        let (producer, consumer) = command_queue.split();
+
+        cortex_m::interrupt::free(|cs| {
+            COMMAND_CONSUMER.borrow(cs).replace(Some(consumer));
+        });
+
+        producer
+    };
+
     {
         let i2s = {
             let pins = (gpiob.pb12, gpiob.pb13, gpioa.pa3, gpioc.pc3);
@@ -597,6 +832,8 @@ fn main() -> ! {
 
     let mut fps = FPS::new();
 
+    let mut settings_page = SettingsPage::default();
+
     // let mut last_keys_state = Keys::empty();
 
     let mut delay = dp.TIM10.delay_us(&clocks);
@@ -631,6 +868,36 @@ fn main() -> ! {
         if now_us - last_controls_update_us > CONTROLS_UPDATE_PERIOD_US {
             if let ControlsState::Changed(changed) = control_panel.tick(now_ms) {
                 // info!("Changed {}", changed);
+                if let EncState::Changed(offset) = changed.red_enc {
+                    settings_page.scroll(offset);
+                }
+                if let EncState::Changed(offset) = changed.green_enc {
+                    let settings = cortex_m::interrupt::free(|cs| {
+                        SYNTH_STATUS
+                            .borrow(cs)
+                            .borrow()
+                            .as_ref()
+                            .unwrap()
+                            .settings
+                            .clone()
+                    });
+                    if let Some(action) = settings_page.adjust(offset, &settings) {
+                        match SynthCommand::from_setting(action) {
+                            Some(command) => {
+                                if commands.push(command).is_err() {
+                                    warn!("Synth command queue is full, dropping {}", command);
+                                }
+                            }
+                            // Bindings learned from MIDI are stored here too
+                            None if settings.to_bytes() != saved_settings.to_bytes() => {
+                                if save_settings(&mut flash, &settings, &mut commands) {
+                                    saved_settings = settings;
+                                }
+                            }
+                            None => {}
+                        }
+                    }
+                }
                 ui.tick(changed.into_events().into_iter());
             }
             last_controls_update_us = now_us;
@@ -643,23 +910,14 @@ fn main() -> ! {
                 .for_each(|(key_index, edge)| {
                     let note: Note = (key_index as u8).try_into().unwrap();
                     let note = note.transpose(60);
-                    match edge {
-                        paw_one::iter::digits::Edge::Rising => cortex_m::interrupt::free(|cs| {
-                            SYNTH
-                                .borrow(cs)
-                                .borrow_mut()
-                                .as_mut()
-                                .unwrap()
-                                .note_on(note)
-                        }),
-                        paw_one::iter::digits::Edge::Falling => cortex_m::interrupt::free(|cs| {
-                            SYNTH
-                                .borrow(cs)
-                                .borrow_mut()
-                                .as_mut()
-                                .unwrap()
-                                .note_off(note)
-                        }),
+                    let command = match edge {
+                        paw_one::iter::digits::Edge::Rising => {
+                            SynthCommand::NoteOn(note, MAX_VELOCITY)
+                        }
+                        paw_one::iter::digits::Edge::Falling => SynthCommand::NoteOff(note),
+                    };
+                    if commands.push(command).is_err() {
+                        warn!("Synth command queue is full, dropping {}", command);
                     }
                 });
             // info!(
@@ -680,18 +938,22 @@ fn main() -> ! {
         if now_ms - last_frame_ms > FPS_MS_PERIOD {
             ui.draw(&mut display);
 
-            let now_playing = cortex_m::interrupt::free(|cs| {
-                SYNTH
-                    .borrow(cs)
-                    .borrow()
-                    .as_ref()
-                    .unwrap()
-                    .active_voices()
-                    .filter_map(|voice| voice.current_note().map(|note| note.to_string()))
-                    .collect::<Vec<_>>()
-                    .join(", ")
+            let status = cortex_m::interrupt::free(|cs| {
+                let mut published = SYNTH_STATUS.borrow(cs).borrow_mut();
+                let published = published.as_mut().unwrap();
+                let status = published.clone();
+                published.midi_queue_high = 0;
+                published.render_cycles_peak = 0;
+                status
             });
 
+            let now_playing = status
+                .notes
+                .iter()
+                .map(|note| note.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+
             TextBox::new(
                 &format!("Now playing: {}", now_playing),
                 Rectangle::new(Point::new(0, 55), Size::new(128, 7)),
@@ -704,6 +966,20 @@ fn main() -> ! {
             .draw(&mut display)
             .unwrap();
 
+            let preset = format!("{}:{} {}", status.bank, status.program, status.preset_name);
+
+            TextBox::new(
+                &preset,
+                Rectangle::new(Point::new(28, 0), Size::new(100, 7)),
+                MonoTextStyleBuilder::new()
+                    .font(&FONT_4X6)
+                    .text_color(BinaryColor::On)
+                    .background_color(BinaryColor::Off)
+                    .build(),
+            )
+            .draw(&mut display)
+            .unwrap();
+
             // Text::new(format!("{}FPS", ), Point::new(x, y), character_style)
             TextBox::new(
                 &format!("{}FPS", fps.value().round() as u32),
@@ -719,10 +995,25 @@ fn main() -> ! {
 
             TextBox::new(
                 &format!(
-                    "UDR: {}",
-                    AUDIO_BUFFER_UNDERRUN_COUNT.load(core::sync::atomic::Ordering::Relaxed)
+                    "UDR: {} CPU: {}% MIDIQ: {}/{}",
+                    AUDIO_BUFFER_UNDERRUN_COUNT.load(core::sync::atomic::Ordering::Relaxed),
+                    status.render_cycles_peak as u64 * 100 / BLOCK_DURATION_CYCLES as u64,
+                    status.midi_queue_high,
+                    MIDI_QUEUE_SIZE
                 ),
-                Rectangle::new(Point::new(0, 25), Size::new(64, 6)),
+                Rectangle::new(Point::new(0, 25), Size::new(128, 6)),
+                MonoTextStyleBuilder::new()
+                    .font(&FONT_4X6)
+                    .text_color(BinaryColor::On)
+                    .background_color(BinaryColor::Off)
+                    .build(),
+            )
+            .draw(&mut display)
+            .unwrap();
+
+            TextBox::new(
+                &settings_page.label(&status.settings, &saved_settings),
+                Rectangle::new(Point::new(0, 40), Size::new(128, 7)),
                 MonoTextStyleBuilder::new()
                     .font(&FONT_4X6)
                     .text_color(BinaryColor::On)
@@ -0,0 +1,44 @@
+/// Controller numbers of MIDI Control Change messages handled by the synth
+pub const BANK_SELECT: u8 = 0;
+pub const MOD_WHEEL: u8 = 1;
+pub const PORTAMENTO_TIME: u8 = 5;
+pub const DATA_ENTRY: u8 = 6;
+pub const VOLUME: u8 = 7;
+pub const PAN: u8 = 10;
+pub const BANK_SELECT_LSB: u8 = 32;
+pub const DATA_ENTRY_LSB: u8 = 38;
+pub const SUSTAIN: u8 = 64;
+pub const SOSTENUTO: u8 = 66;
+/// Last of the pedal switch controllers starting at `SUSTAIN`
+pub const HOLD_2: u8 = 69;
+pub const RESONANCE: u8 = 71;
+pub const RELEASE_TIME: u8 = 72;
+pub const ATTACK_TIME: u8 = 73;
+pub const CUTOFF: u8 = 74;
+pub const DATA_INCREMENT: u8 = 96;
+pub const DATA_DECREMENT: u8 = 97;
+pub const NRPN_LSB: u8 = 98;
+pub const NRPN_MSB: u8 = 99;
+pub const RPN_LSB: u8 = 100;
+pub const RPN_MSB: u8 = 101;
+/// Controllers from this one up are channel mode messages
+pub const CHANNEL_MODE: u8 = 120;
+
+/// Pedal switches are on for values of 64 and above
+pub fn is_on(value: u8) -> bool {
+    value >= 64
+}
+
+/// Controller can be bound to a parameter, bank select, pedal switches, parameter numbers and channel mode messages are reserved
+pub fn is_assignable(control: u8) -> bool {
+    !matches!(
+        control,
+        BANK_SELECT
+            | BANK_SELECT_LSB
+            | DATA_ENTRY
+            | DATA_ENTRY_LSB
+            | SUSTAIN..=HOLD_2
+            | DATA_INCREMENT..=RPN_MSB
+            | CHANNEL_MODE..
+    )
+}
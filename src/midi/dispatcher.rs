@@ -0,0 +1,316 @@
+use super::note::Note;
+
+/// USB-MIDI event packet: cable number and code index, then up to 3 bytes of MIDI message
+pub type Packet = [u8; 4];
+
+/// Channel voice message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+    NoteOn {
+        note: Note,
+        velocity: u8,
+    },
+    /// Also produced for Note On with velocity 0
+    NoteOff {
+        note: Note,
+        velocity: u8,
+    },
+    ControlChange {
+        control: u8,
+        value: u8,
+    },
+    ProgramChange(u8),
+    /// 14-bit wheel position, 0x2000 is the center
+    PitchBend(u16),
+    ChannelPressure(u8),
+    PolyPressure {
+        note: Note,
+        pressure: u8,
+    },
+}
+
+/// Which channels the dispatcher listens to, channels are numbered from 0
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum ChannelFilter {
+    #[default]
+    Omni,
+    Channel(u8),
+}
+
+impl ChannelFilter {
+    pub fn accepts(&self, channel: u8) -> bool {
+        match *self {
+            ChannelFilter::Omni => true,
+            ChannelFilter::Channel(accepted) => accepted == channel,
+        }
+    }
+}
+
+/// Turns raw MIDI bytes into channel voice events, keeping running status between messages
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dispatcher {
+    filter: ChannelFilter,
+    /// Current running status, `None` after system messages until a new status byte
+    status: Option<u8>,
+    data: [u8; 2],
+    len: usize,
+}
+
+impl Dispatcher {
+    pub const fn new(filter: ChannelFilter) -> Self {
+        Self {
+            filter,
+            status: None,
+            data: [0; 2],
+            len: 0,
+        }
+    }
+
+    pub fn filter(&self) -> ChannelFilter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: ChannelFilter) {
+        self.filter = filter;
+    }
+
+    /// Feed USB-MIDI event packet, only channel voice messages are handled
+    pub fn feed_packet(&mut self, packet: Packet, mut f: impl FnMut(u8, Event)) {
+        // Packets always carry complete messages
+        self.status = None;
+        self.len = 0;
+
+        let len = match packet[0] & 0x0F {
+            0x8..=0xB | 0xE => 3,
+            0xC | 0xD => 2,
+            _ => return,
+        };
+
+        for &byte in &packet[1..1 + len] {
+            self.feed(byte, &mut f);
+        }
+    }
+
+    /// Feed single byte of MIDI stream, `f` is called with channel and event for every complete message
+    pub fn feed(&mut self, byte: u8, mut f: impl FnMut(u8, Event)) {
+        match byte {
+            // Real-time messages may appear anywhere and do not affect running status
+            0xF8..=0xFF => {}
+            // System common and exclusive messages cancel running status
+            0xF0..=0xF7 => {
+                self.status = None;
+                self.len = 0;
+            }
+            0x80..=0xEF => {
+                self.status = Some(byte);
+                self.len = 0;
+            }
+            _ => {
+                let Some(status) = self.status else {
+                    return;
+                };
+
+                self.data[self.len] = byte;
+                self.len += 1;
+                if self.len < data_len(status) {
+                    return;
+                }
+                self.len = 0;
+
+                let channel = status & 0x0F;
+                if !self.filter.accepts(channel) {
+                    return;
+                }
+                if let Some(event) = decode(status, self.data) {
+                    f(channel, event);
+                }
+            }
+        }
+    }
+}
+
+/// Count of data bytes following channel voice status
+fn data_len(status: u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        _ => 2,
+    }
+}
+
+fn decode(status: u8, [data1, data2]: [u8; 2]) -> Option<Event> {
+    let event = match status & 0xF0 {
+        0x80 => Event::NoteOff {
+            note: Note::try_from(data1).ok()?,
+            velocity: data2,
+        },
+        0x90 if data2 == 0 => Event::NoteOff {
+            note: Note::try_from(data1).ok()?,
+            velocity: 64,
+        },
+        0x90 => Event::NoteOn {
+            note: Note::try_from(data1).ok()?,
+            velocity: data2,
+        },
+        0xA0 => Event::PolyPressure {
+            note: Note::try_from(data1).ok()?,
+            pressure: data2,
+        },
+        0xB0 => Event::ControlChange {
+            control: data1,
+            value: data2,
+        },
+        0xC0 => Event::ProgramChange(data1),
+        0xD0 => Event::ChannelPressure(data1),
+        0xE0 => Event::PitchBend(((data2 as u16) << 7) | data1 as u16),
+        _ => return None,
+    };
+
+    Some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Events produced by feeding `bytes` as a MIDI stream
+    fn stream(dispatcher: &mut Dispatcher, bytes: &[u8]) -> heapless::Vec<(u8, Event), 16> {
+        let mut events = heapless::Vec::new();
+        for &byte in bytes {
+            dispatcher.feed(byte, |channel, event| {
+                events.push((channel, event)).unwrap()
+            });
+        }
+        events
+    }
+
+    #[test]
+    fn channel_voice_messages() {
+        let mut dispatcher = Dispatcher::default();
+        let events = stream(
+            &mut dispatcher,
+            &[
+                0x90, 69, 100, // Note On
+                0x81, 69, 10, // Note Off
+                0xB2, 64, 127, // Sustain on
+                0xC3, 5, // Program Change
+                0xD4, 90, // Channel pressure
+                0xA5, 60, 33, // Poly pressure
+                0xE6, 0x7F, 0x7F, // Bend fully up
+            ],
+        );
+
+        assert_eq!(
+            events,
+            [
+                (
+                    0,
+                    Event::NoteOn {
+                        note: Note::A4,
+                        velocity: 100
+                    }
+                ),
+                (
+                    1,
+                    Event::NoteOff {
+                        note: Note::A4,
+                        velocity: 10
+                    }
+                ),
+                (
+                    2,
+                    Event::ControlChange {
+                        control: 64,
+                        value: 127
+                    }
+                ),
+                (3, Event::ProgramChange(5)),
+                (4, Event::ChannelPressure(90)),
+                (
+                    5,
+                    Event::PolyPressure {
+                        note: Note::C4,
+                        pressure: 33
+                    }
+                ),
+                (6, Event::PitchBend(0x3FFF)),
+            ]
+        );
+    }
+
+    #[test]
+    fn running_status_and_zero_velocity() {
+        let mut dispatcher = Dispatcher::default();
+        // Real-time clock in the middle of a message is ignored
+        let events = stream(&mut dispatcher, &[0x90, 60, 100, 64, 0xF8, 90, 60, 0]);
+
+        assert_eq!(
+            events,
+            [
+                (
+                    0,
+                    Event::NoteOn {
+                        note: Note::C4,
+                        velocity: 100
+                    }
+                ),
+                (
+                    0,
+                    Event::NoteOn {
+                        note: Note::E4,
+                        velocity: 90
+                    }
+                ),
+                (
+                    0,
+                    Event::NoteOff {
+                        note: Note::C4,
+                        velocity: 64
+                    }
+                ),
+            ]
+        );
+
+        // SysEx cancels running status, its data is not mistaken for notes
+        let events = stream(&mut dispatcher, &[0xF0, 0x7E, 60, 0xF7, 60, 100]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn channel_filter() {
+        let mut dispatcher = Dispatcher::new(ChannelFilter::Channel(2));
+        let events = stream(
+            &mut dispatcher,
+            &[0x90, 60, 100, 0x92, 62, 100, 0x9F, 64, 100, 0x92, 65, 100],
+        );
+
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|&(channel, _)| channel == 2));
+    }
+
+    #[test]
+    fn usb_packets() {
+        let mut dispatcher = Dispatcher::default();
+        let mut events = heapless::Vec::<_, 4>::new();
+        for packet in [
+            [0x09, 0x90, 60, 100],
+            // SysEx start is skipped
+            [0x04, 0xF0, 0x7E, 0x7F],
+            [0x0C, 0xC0, 7, 0],
+            [0x0E, 0xE0, 0x00, 0x40],
+        ] {
+            dispatcher.feed_packet(packet, |_, event| events.push(event).unwrap());
+        }
+
+        assert_eq!(
+            events,
+            [
+                Event::NoteOn {
+                    note: Note::C4,
+                    velocity: 100
+                },
+                Event::ProgramChange(7),
+                Event::PitchBend(0x2000),
+            ]
+        );
+    }
+}
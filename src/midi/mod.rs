@@ -1,4 +1,7 @@
+pub mod cc;
+pub mod dispatcher;
 pub mod note;
+pub mod param;
 
 use defmt::{debug, warn};
 use stm32f4xx_hal::otg_fs::{UsbBus, UsbBusType, USB};
@@ -7,15 +10,12 @@ use usb_device::{
     device::{StringDescriptors, UsbDevice, UsbDeviceBuilder, UsbVidPid},
 };
 use usbd_midi::{
-    data::{
-        usb::constants::{USB_AUDIO_CLASS, USB_MIDISTREAMING_SUBCLASS},
-        usb_midi::{
-            midi_packet_reader::MidiPacketBufferReader, usb_midi_event_packet::UsbMidiEventPacket,
-        },
-    },
+    data::usb::constants::{USB_AUDIO_CLASS, USB_MIDISTREAMING_SUBCLASS},
     midi_device::MidiClass,
 };
 
+use self::dispatcher::Packet;
+
 pub struct UsbMidi<'a> {
     midi: MidiClass<'a, UsbBusType>,
     usb_dev: UsbDevice<'a, UsbBusType>,
@@ -38,24 +38,21 @@ impl<'a> UsbMidi<'a> {
         Self { midi, usb_dev }
     }
 
-    pub fn poll(&mut self, f: impl Fn(UsbMidiEventPacket)) {
+    /// Poll USB device, `f` is called with every raw USB-MIDI packet received, parsing is left to `Dispatcher`
+    pub fn poll(&mut self, mut f: impl FnMut(Packet)) {
         if self.usb_dev.poll(&mut [&mut self.midi]) {
             let mut buffer = [0; 64];
 
             if let Ok(size) = self.midi.read(&mut buffer) {
-                let buffer_reader = MidiPacketBufferReader::new(&buffer, size);
-
-                for packet in buffer_reader.into_iter() {
-                    match packet {
-                        Ok(packet) => {
-                            debug!("MIDI Packet: {}", format!("{:?}", packet).as_str());
-
-                            f(packet);
-                        }
-                        Err(err) => {
-                            warn!("MIDI Packet ERROR: {}", format!("{:?}", err).as_str());
-                        }
-                    }
+                if size % 4 != 0 {
+                    warn!("MIDI Packet ERROR: incomplete packet of {} bytes", size % 4);
+                }
+
+                for packet in buffer[..size].chunks_exact(4) {
+                    let packet = [packet[0], packet[1], packet[2], packet[3]];
+                    debug!("MIDI Packet: {:x}", packet);
+
+                    f(packet);
                 }
             }
         }
@@ -0,0 +1,341 @@
+use super::cc;
+
+/// Registered parameter numbers understood by the synth
+pub const RPN_BEND_RANGE: u16 = 0;
+pub const RPN_FINE_TUNING: u16 = 1;
+pub const RPN_COARSE_TUNING: u16 = 2;
+/// Deselects the current parameter, so stray data entry does not change anything
+pub const RPN_NULL: u16 = 0x3FFF;
+/// Center of 14-bit values, e.g. no fine tuning
+pub const VALUE_CENTER: u16 = 0x2000;
+
+const CHANNELS: usize = 16;
+/// Controllers 0..32 have their LSB sent as controller + 32
+const HIGH_RES_CONTROLS: u8 = 32;
+
+/// Control Change messages assembled into 14-bit values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum ParamChange {
+    /// Controller value scaled to 14 bits, LSB controllers are reported as their MSB controller
+    Control {
+        control: u8,
+        value: u16,
+    },
+    Rpn {
+        param: u16,
+        value: u16,
+    },
+    Nrpn {
+        param: u16,
+        value: u16,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Selected {
+    #[default]
+    None,
+    Rpn,
+    Nrpn,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ChannelState {
+    /// Last MSB of each high resolution controller
+    msb: [u8; HIGH_RES_CONTROLS as usize],
+    rpn: u16,
+    nrpn: u16,
+    selected: Selected,
+    /// Data entry value of the selected parameter
+    data: u16,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self {
+            msb: [0; HIGH_RES_CONTROLS as usize],
+            rpn: RPN_NULL,
+            nrpn: RPN_NULL,
+            selected: Selected::None,
+            data: 0,
+        }
+    }
+}
+
+impl ChannelState {
+    fn selected(&self) -> Option<(Selected, u16)> {
+        match self.selected {
+            Selected::Rpn if self.rpn != RPN_NULL => Some((Selected::Rpn, self.rpn)),
+            Selected::Nrpn if self.nrpn != RPN_NULL => Some((Selected::Nrpn, self.nrpn)),
+            _ => None,
+        }
+    }
+
+    fn data_change(&self) -> Option<ParamChange> {
+        let value = self.data;
+        self.selected().map(|(selected, param)| match selected {
+            Selected::Nrpn => ParamChange::Nrpn { param, value },
+            _ => ParamChange::Rpn { param, value },
+        })
+    }
+}
+
+/// Per-channel state for 14-bit controllers and RPN/NRPN sequences
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParamParser {
+    channels: [ChannelState; CHANNELS],
+}
+
+impl ParamParser {
+    /// Feed Control Change message, `None` if it only updated parser state
+    pub fn feed(&mut self, channel: u8, control: u8, value: u8) -> Option<ParamChange> {
+        let state = &mut self.channels[channel as usize % CHANNELS];
+        let value = value & 0x7F;
+
+        match control {
+            cc::RPN_MSB => {
+                state.rpn = set_msb(state.rpn, value);
+                state.selected = Selected::Rpn;
+                None
+            }
+            cc::RPN_LSB => {
+                state.rpn = set_lsb(state.rpn, value);
+                state.selected = Selected::Rpn;
+                None
+            }
+            cc::NRPN_MSB => {
+                state.nrpn = set_msb(state.nrpn, value);
+                state.selected = Selected::Nrpn;
+                None
+            }
+            cc::NRPN_LSB => {
+                state.nrpn = set_lsb(state.nrpn, value);
+                state.selected = Selected::Nrpn;
+                None
+            }
+            // New MSB resets LSB as required by the specification
+            cc::DATA_ENTRY => {
+                state.data = (value as u16) << 7;
+                state.data_change()
+            }
+            cc::DATA_ENTRY_LSB => {
+                state.data = set_lsb(state.data, value);
+                state.data_change()
+            }
+            cc::DATA_INCREMENT => {
+                state.data = (state.data + 1).min(0x3FFF);
+                state.data_change()
+            }
+            cc::DATA_DECREMENT => {
+                state.data = state.data.saturating_sub(1);
+                state.data_change()
+            }
+            // Bank numbers are not scaled like continuous controllers
+            cc::BANK_SELECT => {
+                state.msb[control as usize] = value;
+                Some(ParamChange::Control {
+                    control,
+                    value: (value as u16) << 7,
+                })
+            }
+            0..=31 => {
+                state.msb[control as usize] = value;
+                Some(ParamChange::Control {
+                    control,
+                    value: scale(value),
+                })
+            }
+            32..=63 => {
+                let control = control - HIGH_RES_CONTROLS;
+                Some(ParamChange::Control {
+                    control,
+                    value: ((state.msb[control as usize] as u16) << 7) | value as u16,
+                })
+            }
+            _ => Some(ParamChange::Control {
+                control,
+                value: scale(value),
+            }),
+        }
+    }
+}
+
+fn set_msb(value: u16, msb: u8) -> u16 {
+    ((msb as u16) << 7) | (value & 0x7F)
+}
+
+fn set_lsb(value: u16, lsb: u8) -> u16 {
+    (value & !0x7F) | lsb as u16
+}
+
+/// 7-bit value spread over the whole 14-bit range, so 127 is the maximum
+fn scale(value: u8) -> u16 {
+    ((value as u16) << 7) | value as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::dispatcher::Dispatcher, *};
+    use crate::midi::dispatcher::Event;
+
+    /// Parameter changes produced by `bytes` of MIDI stream
+    fn parse(bytes: &[u8]) -> heapless::Vec<(u8, ParamChange), 16> {
+        let mut dispatcher = Dispatcher::default();
+        let mut parser = ParamParser::default();
+        let mut changes = heapless::Vec::new();
+        for &byte in bytes {
+            dispatcher.feed(byte, |channel, event| {
+                if let Event::ControlChange { control, value } = event {
+                    if let Some(change) = parser.feed(channel, control, value) {
+                        changes.push((channel, change)).unwrap();
+                    }
+                }
+            });
+        }
+        changes
+    }
+
+    #[test]
+    fn high_resolution_control() {
+        let changes = parse(&[0xB0, 1, 0x40, 33, 0x05, 7, 127]);
+
+        assert_eq!(
+            changes,
+            [
+                (
+                    0,
+                    ParamChange::Control {
+                        control: 1,
+                        value: scale(0x40)
+                    }
+                ),
+                (
+                    0,
+                    ParamChange::Control {
+                        control: 1,
+                        value: 0x2005
+                    }
+                ),
+                (
+                    0,
+                    ParamChange::Control {
+                        control: 7,
+                        value: 0x3FFF
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rpn_with_running_status() {
+        // Bend range of 12 semitones and 50 cents, then the null RPN
+        let changes = parse(&[
+            0xB3, 101, 0, 100, 0, 6, 12, 38, 50, 101, 127, 100, 127, 6, 1,
+        ]);
+
+        assert_eq!(
+            changes,
+            [
+                (
+                    3,
+                    ParamChange::Rpn {
+                        param: RPN_BEND_RANGE,
+                        value: 12 << 7
+                    }
+                ),
+                (
+                    3,
+                    ParamChange::Rpn {
+                        param: RPN_BEND_RANGE,
+                        value: (12 << 7) | 50
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn interleaved_channels() {
+        let changes = parse(&[
+            0xB0, 101, 0, // Channel 0 selects coarse tuning
+            0xB1, 99, 1, // Channel 1 selects NRPN
+            0xB0, 100, 2, //
+            0xB1, 98, 3, 1, 10, // 14-bit mod wheel MSB between NRPN and data entry
+            0xB0, 6, 66, //
+            0xB1, 6, 100, 33, 5, //
+            0xB1, 96, 0, // Increment
+        ]);
+
+        assert_eq!(
+            changes,
+            [
+                (
+                    1,
+                    ParamChange::Control {
+                        control: 1,
+                        value: scale(10)
+                    }
+                ),
+                (
+                    0,
+                    ParamChange::Rpn {
+                        param: RPN_COARSE_TUNING,
+                        value: 66 << 7
+                    }
+                ),
+                (
+                    1,
+                    ParamChange::Nrpn {
+                        param: (1 << 7) | 3,
+                        value: 100 << 7
+                    }
+                ),
+                (
+                    1,
+                    ParamChange::Control {
+                        control: 1,
+                        value: (10 << 7) | 5
+                    }
+                ),
+                (
+                    1,
+                    ParamChange::Nrpn {
+                        param: (1 << 7) | 3,
+                        value: (100 << 7) + 1
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn bank_select_is_not_scaled() {
+        let changes = parse(&[0xB0, 0, 2, 32, 5]);
+
+        assert_eq!(
+            changes,
+            [
+                (
+                    0,
+                    ParamChange::Control {
+                        control: cc::BANK_SELECT,
+                        value: 2 << 7
+                    }
+                ),
+                (
+                    0,
+                    ParamChange::Control {
+                        control: cc::BANK_SELECT,
+                        value: (2 << 7) | 5
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn data_entry_without_parameter_is_ignored() {
+        assert!(parse(&[0xB0, 6, 10, 38, 10, 96, 0]).is_empty());
+    }
+}
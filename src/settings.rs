@@ -0,0 +1,69 @@
+use crate::synth::{
+    cc_map::{CcMap, CC_BINDING_BYTES, CC_MAP_SLOTS},
+    preset::PresetChange,
+};
+
+/// Marks stored settings, erased flash reads as 0xFF
+const MAGIC: [u8; 4] = *b"PAWS";
+/// Format version, settings of other versions are ignored
+const VERSION: u8 = 2;
+const HEADER_BYTES: usize = MAGIC.len() + 1;
+const CC_MAP_END: usize = HEADER_BYTES + CC_MAP_SLOTS * CC_BINDING_BYTES;
+/// Size of serialized `Settings`
+pub const SETTINGS_BYTES: usize = CC_MAP_END + 1;
+
+/// Device settings kept across power cycles
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub cc_map: CcMap,
+    pub preset_change: PresetChange,
+}
+
+impl Settings {
+    pub fn to_bytes(&self) -> [u8; SETTINGS_BYTES] {
+        let mut bytes = [0; SETTINGS_BYTES];
+        bytes[..MAGIC.len()].copy_from_slice(&MAGIC);
+        bytes[MAGIC.len()] = VERSION;
+        bytes[HEADER_BYTES..CC_MAP_END].copy_from_slice(&self.cc_map.to_bytes());
+        bytes[CC_MAP_END] = self.preset_change.into();
+        bytes
+    }
+
+    /// Deserialize settings written by `to_bytes`, `None` if `bytes` don't start with settings of this version
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; SETTINGS_BYTES] = bytes.get(..SETTINGS_BYTES)?.try_into().ok()?;
+        if bytes[..MAGIC.len()] != MAGIC || bytes[MAGIC.len()] != VERSION {
+            return None;
+        }
+
+        Some(Self {
+            cc_map: CcMap::from_bytes(bytes[HEADER_BYTES..CC_MAP_END].try_into().ok()?),
+            preset_change: PresetChange::try_from(bytes[CC_MAP_END]).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::cc_map::SynthParam;
+
+    #[test]
+    fn serialization_roundtrip() {
+        let mut settings = Settings::default();
+        settings.cc_map.bind(20, SynthParam::GlideTime);
+        settings.preset_change = PresetChange::Cut;
+
+        assert_eq!(Settings::from_bytes(&settings.to_bytes()), Some(settings));
+    }
+
+    #[test]
+    fn erased_flash_is_not_settings() {
+        assert_eq!(Settings::from_bytes(&[0xFF; SETTINGS_BYTES]), None);
+        assert_eq!(Settings::from_bytes(&[]), None);
+
+        let mut bytes = Settings::default().to_bytes();
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert_eq!(Settings::from_bytes(&bytes), None);
+    }
+}
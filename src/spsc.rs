@@ -0,0 +1,325 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Lowest and highest fill level seen since the last reset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub struct Watermarks {
+    pub low: usize,
+    pub high: usize,
+}
+
+/// Lock-free single-producer single-consumer queue, `N` must be a power of two.
+/// Split into `Producer` and `Consumer` handles that can live in different interrupts.
+pub struct Queue<T, const N: usize> {
+    buffer: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// Position of the next item to pop, only written by consumer
+    head: AtomicUsize,
+    /// Position of the next item to push, only written by producer
+    tail: AtomicUsize,
+    low_watermark: AtomicUsize,
+    high_watermark: AtomicUsize,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T, const N: usize> Queue<T, N> {
+    const CAPACITY_CHECK: () = assert!(N.is_power_of_two(), "Queue size must be a power of two");
+
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::CAPACITY_CHECK;
+
+        Self {
+            // Array of `MaybeUninit` needs no initialization
+            buffer: UnsafeCell::new(unsafe { MaybeUninit::uninit().assume_init() }),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            low_watermark: AtomicUsize::new(0),
+            high_watermark: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Current fill level
+    pub fn len(&self) -> usize {
+        self.tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    pub fn watermarks(&self) -> Watermarks {
+        Watermarks {
+            low: self.low_watermark.load(Ordering::Relaxed),
+            high: self.high_watermark.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Restart watermark statistics from the current fill level, only the consumer may call it as it owns the low watermark
+    fn reset_watermarks(&self) {
+        let len = self.len();
+        self.low_watermark.store(len, Ordering::Relaxed);
+        self.high_watermark.store(len, Ordering::SeqCst);
+        // Maximum of a push between reading the length and the store above has been overwritten
+        self.high_watermark.fetch_max(self.len(), Ordering::SeqCst);
+    }
+
+    fn slot(&self, position: usize) -> *mut MaybeUninit<T> {
+        // `N` is a power of two, so wrapping position counters stay consistent with the index
+        unsafe { (*self.buffer.get()).as_mut_ptr().add(position % N) }
+    }
+}
+
+impl<T, const N: usize> Default for Queue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for Queue<T, N> {
+    fn drop(&mut self) {
+        let tail = *self.tail.get_mut();
+        let mut head = *self.head.get_mut();
+
+        while head != tail {
+            unsafe { (*self.slot(head)).assume_init_drop() };
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>,
+}
+
+unsafe impl<T: Send, const N: usize> Send for Producer<'_, T, N> {}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Push item to the back of the queue, giving it back if the queue is full
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let head = self.queue.head.load(Ordering::Acquire);
+
+        let len = tail.wrapping_sub(head);
+        if len == N {
+            return Err(item);
+        }
+
+        unsafe { (*self.queue.slot(tail)).write(item) };
+        self.queue
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+
+        self.queue
+            .high_watermark
+            .fetch_max(len + 1, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    pub fn watermarks(&self) -> Watermarks {
+        self.queue.watermarks()
+    }
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a Queue<T, N>,
+}
+
+unsafe impl<T: Send, const N: usize> Send for Consumer<'_, T, N> {}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    /// Pop item from the front of the queue
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        let tail = self.queue.tail.load(Ordering::Acquire);
+
+        let len = tail.wrapping_sub(head);
+        if len == 0 {
+            return None;
+        }
+
+        let item = unsafe { (*self.queue.slot(head)).assume_init_read() };
+        self.queue
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+
+        self.queue
+            .low_watermark
+            .fetch_min(len - 1, Ordering::Relaxed);
+
+        Some(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    pub fn watermarks(&self) -> Watermarks {
+        self.queue.watermarks()
+    }
+
+    /// Restart watermark statistics from the current fill level
+    pub fn reset_watermarks(&mut self) {
+        self.queue.reset_watermarks();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{sync::Arc, thread, vec::Vec};
+
+    use super::*;
+
+    #[test]
+    fn push_pop_in_order() {
+        let mut queue = Queue::<u32, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        assert_eq!(consumer.pop(), None);
+
+        for round in 0..10 {
+            for i in 0..3 {
+                producer.push(round * 10 + i).unwrap();
+            }
+            assert_eq!(consumer.len(), 3);
+
+            for i in 0..3 {
+                assert_eq!(consumer.pop(), Some(round * 10 + i));
+            }
+            assert!(producer.is_empty());
+        }
+    }
+
+    #[test]
+    fn full_queue_rejects_push() {
+        let mut queue = Queue::<u8, 2>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        producer.push(1).unwrap();
+        producer.push(2).unwrap();
+        assert!(producer.is_full());
+        assert_eq!(producer.push(3), Err(3));
+
+        assert_eq!(consumer.pop(), Some(1));
+        producer.push(3).unwrap();
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+    }
+
+    #[test]
+    fn watermarks() {
+        let mut queue = Queue::<u8, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        for i in 0..6 {
+            producer.push(i).unwrap();
+        }
+        consumer.reset_watermarks();
+        assert_eq!(consumer.watermarks(), Watermarks { low: 6, high: 6 });
+
+        consumer.pop();
+        consumer.pop();
+        consumer.pop();
+        producer.push(0).unwrap();
+
+        assert_eq!(producer.watermarks(), Watermarks { low: 3, high: 6 });
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        assert_eq!(producer.watermarks(), Watermarks { low: 3, high: 8 });
+    }
+
+    #[test]
+    fn drops_remaining_items() {
+        let item = Arc::new(());
+        {
+            let mut queue = Queue::<Arc<()>, 4>::new();
+            let (mut producer, mut consumer) = queue.split();
+            for _ in 0..3 {
+                producer.push(item.clone()).unwrap();
+            }
+            consumer.pop();
+            assert_eq!(Arc::strong_count(&item), 3);
+        }
+
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn concurrent_producer_and_consumer() {
+        const COUNT: u32 = 200_000;
+
+        let mut queue = Queue::<u32, 64>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        let received = thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..COUNT {
+                    let mut item = i;
+                    while let Err(rejected) = producer.push(item) {
+                        item = rejected;
+                        thread::yield_now();
+                    }
+                }
+            });
+
+            let consumer = scope.spawn(move || {
+                let mut received = Vec::with_capacity(COUNT as usize);
+                while received.len() < COUNT as usize {
+                    match consumer.pop() {
+                        Some(item) => received.push(item),
+                        None => thread::yield_now(),
+                    }
+                }
+                received
+            });
+
+            consumer.join().unwrap()
+        });
+
+        assert!(received.iter().copied().eq(0..COUNT));
+        assert!(queue.is_empty());
+        assert!(queue.watermarks().high <= 64);
+    }
+}
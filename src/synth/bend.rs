@@ -0,0 +1,121 @@
+use super::smooth::Smoother;
+
+/// Time constant of pitch bend smoothing in ms
+const BEND_SMOOTHING_MS: f32 = 5.0;
+/// Pitch wheel value at rest
+pub const BEND_CENTER: u16 = 0x2000;
+
+/// Pitch bend range in semitones for each direction of the wheel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BendRange {
+    pub up: f32,
+    pub down: f32,
+}
+
+impl BendRange {
+    /// Pitch offset in semitones for wheel position in -1..1 range
+    pub fn semitones(&self, position: f32) -> f32 {
+        if position >= 0.0 {
+            position * self.up
+        } else {
+            position * self.down
+        }
+    }
+}
+
+impl Default for BendRange {
+    fn default() -> Self {
+        Self { up: 2.0, down: 2.0 }
+    }
+}
+
+/// Pitch wheel position smoothed at control rate, so steps of the 14-bit value are not heard
+#[derive(Clone, Copy, Debug)]
+pub struct PitchBend {
+    /// Position in -1..1 range
+    position: Smoother,
+}
+
+impl PitchBend {
+    /// Smoother updated every `period` samples
+    pub fn new(period: usize) -> Self {
+        Self {
+            position: Smoother::new(BEND_SMOOTHING_MS, period),
+        }
+    }
+
+    /// Set wheel from 14-bit MIDI value, `BEND_CENTER` is no bend
+    pub fn set(&mut self, value: u16) {
+        let value = value.min(0x3FFF) as f32 - BEND_CENTER as f32;
+        // Up range is one step shorter, so the highest value reaches full bend too
+        self.position.set_target(if value >= 0.0 {
+            value / (0x3FFF - BEND_CENTER) as f32
+        } else {
+            value / BEND_CENTER as f32
+        });
+    }
+
+    /// Target position in -1..1 range
+    pub fn target(&self) -> f32 {
+        self.position.target()
+    }
+
+    pub fn reset(&mut self) {
+        self.position.reset(0.0);
+    }
+
+    /// Move towards the target by one update, returns pitch offset in semitones
+    pub fn advance(&mut self, range: &BendRange) -> f32 {
+        range.semitones(self.position.advance())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+
+    #[test]
+    fn wheel_positions() {
+        let mut bend = PitchBend::new(32);
+
+        bend.set(BEND_CENTER);
+        assert_eq!(bend.target(), 0.0);
+        bend.set(0);
+        assert_eq!(bend.target(), -1.0);
+        bend.set(0x3FFF);
+        assert_eq!(bend.target(), 1.0);
+    }
+
+    #[test]
+    fn asymmetric_range() {
+        let range = BendRange {
+            up: 2.0,
+            down: 12.0,
+        };
+
+        assert_eq!(range.semitones(1.0), 2.0);
+        assert_eq!(range.semitones(-1.0), -12.0);
+        assert_eq!(range.semitones(-0.5), -6.0);
+    }
+
+    #[test]
+    fn change_is_smoothed() {
+        let range = BendRange::default();
+        let mut bend = PitchBend::new(32);
+        bend.set(0x3FFF);
+
+        let mut last = 0.0;
+        for _ in 0..20 {
+            let pitch = bend.advance(&range);
+            assert!(pitch > last && pitch - last < 0.3);
+            last = pitch;
+        }
+
+        // Settles after 50ms
+        for _ in 0..SAMPLE_RATE as usize / 20 / 32 {
+            last = bend.advance(&range);
+        }
+        assert!((last - 2.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,267 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::midi::cc;
+
+pub const CC_MAP_SLOTS: usize = 16;
+/// Size of serialized `CcBinding`
+pub const CC_BINDING_BYTES: usize = 2;
+/// Byte of a serialized empty slot
+const EMPTY_SLOT: u8 = 0xFF;
+
+/// Synth parameter controllable by MIDI CC
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum SynthParam {
+    Cutoff,
+    Resonance,
+    FilterEnvAmount,
+    AmpAttack,
+    AmpDecay,
+    AmpSustain,
+    AmpRelease,
+    GlideTime,
+    Pan,
+    Volume,
+}
+
+impl SynthParam {
+    pub const ALL: [Self; 10] = [
+        Self::Cutoff,
+        Self::Resonance,
+        Self::FilterEnvAmount,
+        Self::AmpAttack,
+        Self::AmpDecay,
+        Self::AmpSustain,
+        Self::AmpRelease,
+        Self::GlideTime,
+        Self::Pan,
+        Self::Volume,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SynthParam::Cutoff => "Cutoff",
+            SynthParam::Resonance => "Reso",
+            SynthParam::FilterEnvAmount => "FEnv",
+            SynthParam::AmpAttack => "Attack",
+            SynthParam::AmpDecay => "Decay",
+            SynthParam::AmpSustain => "Sustain",
+            SynthParam::AmpRelease => "Release",
+            SynthParam::GlideTime => "Glide",
+            SynthParam::Pan => "Pan",
+            SynthParam::Volume => "Volume",
+        }
+    }
+}
+
+impl core::fmt::Display for SynthParam {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct CcBinding {
+    pub control: u8,
+    pub param: SynthParam,
+}
+
+impl CcBinding {
+    pub fn to_bytes(&self) -> [u8; CC_BINDING_BYTES] {
+        [self.control, self.param.into()]
+    }
+
+    /// Deserialize binding written by `to_bytes`, `None` for an empty slot or unknown parameter
+    pub fn from_bytes(bytes: [u8; CC_BINDING_BYTES]) -> Option<Self> {
+        if bytes[0] > 127 {
+            return None;
+        }
+
+        Some(Self {
+            control: bytes[0],
+            param: SynthParam::try_from(bytes[1]).ok()?,
+        })
+    }
+}
+
+/// Bindings of controller numbers to synth parameters, with MIDI learn.
+/// A controller drives at most one parameter and each parameter follows at most one controller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CcMap {
+    slots: [Option<CcBinding>; CC_MAP_SLOTS],
+    /// Parameter waiting to be bound to the next assignable controller moved
+    learning: Option<SynthParam>,
+}
+
+impl CcMap {
+    pub fn empty() -> Self {
+        Self {
+            slots: [None; CC_MAP_SLOTS],
+            learning: None,
+        }
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = &CcBinding> {
+        self.slots.iter().flatten()
+    }
+
+    /// Parameter bound to controller
+    pub fn param(&self, control: u8) -> Option<SynthParam> {
+        self.bindings()
+            .find(|binding| binding.control == control)
+            .map(|binding| binding.param)
+    }
+
+    /// Controller bound to parameter
+    pub fn control(&self, param: SynthParam) -> Option<u8> {
+        self.bindings()
+            .find(|binding| binding.param == param)
+            .map(|binding| binding.control)
+    }
+
+    /// Bind controller to parameter replacing their previous bindings, `false` if controller is reserved or the map is full
+    pub fn bind(&mut self, control: u8, param: SynthParam) -> bool {
+        if !cc::is_assignable(control) {
+            return false;
+        }
+
+        self.unbind(param);
+        for slot in self.slots.iter_mut() {
+            if slot.is_some_and(|binding| binding.control == control) {
+                *slot = None;
+            }
+        }
+
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(CcBinding { control, param });
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn unbind(&mut self, param: SynthParam) {
+        for slot in self.slots.iter_mut() {
+            if slot.is_some_and(|binding| binding.param == param) {
+                *slot = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = [None; CC_MAP_SLOTS];
+    }
+
+    /// Bind `param` to the next assignable controller received
+    pub fn start_learn(&mut self, param: SynthParam) {
+        self.learning = Some(param);
+    }
+
+    pub fn cancel_learn(&mut self) {
+        self.learning = None;
+    }
+
+    pub fn learning(&self) -> Option<SynthParam> {
+        self.learning
+    }
+
+    /// Parameter driven by a received controller, completing MIDI learn first if it is active
+    pub fn handle(&mut self, control: u8) -> Option<SynthParam> {
+        if let Some(param) = self.learning {
+            if self.bind(control, param) {
+                self.learning = None;
+            }
+        }
+
+        self.param(control)
+    }
+
+    pub fn to_bytes(&self) -> [u8; CC_MAP_SLOTS * CC_BINDING_BYTES] {
+        let mut bytes = [EMPTY_SLOT; CC_MAP_SLOTS * CC_BINDING_BYTES];
+        for (chunk, binding) in bytes
+            .chunks_exact_mut(CC_BINDING_BYTES)
+            .zip(self.slots.iter())
+        {
+            if let Some(binding) = binding {
+                chunk.copy_from_slice(&binding.to_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Deserialize map written by `to_bytes`, invalid slots are left empty
+    pub fn from_bytes(bytes: &[u8; CC_MAP_SLOTS * CC_BINDING_BYTES]) -> Self {
+        let mut map = Self::empty();
+        for (slot, chunk) in map
+            .slots
+            .iter_mut()
+            .zip(bytes.chunks_exact(CC_BINDING_BYTES))
+        {
+            *slot = CcBinding::from_bytes([chunk[0], chunk[1]]);
+        }
+        map
+    }
+}
+
+impl Default for CcMap {
+    /// Sound controllers recommended by the MIDI specification
+    fn default() -> Self {
+        let mut map = Self::empty();
+        map.bind(cc::CUTOFF, SynthParam::Cutoff);
+        map.bind(cc::RESONANCE, SynthParam::Resonance);
+        map.bind(cc::ATTACK_TIME, SynthParam::AmpAttack);
+        map.bind(cc::RELEASE_TIME, SynthParam::AmpRelease);
+        map.bind(cc::PORTAMENTO_TIME, SynthParam::GlideTime);
+        map.bind(cc::PAN, SynthParam::Pan);
+        map.bind(cc::VOLUME, SynthParam::Volume);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learn_binds_next_controller() {
+        let mut map = CcMap::empty();
+        assert_eq!(map.handle(20), None);
+
+        map.start_learn(SynthParam::Cutoff);
+        // Reserved controllers do not complete learning
+        assert_eq!(map.handle(cc::DATA_ENTRY), None);
+        assert_eq!(map.handle(cc::SOSTENUTO), None);
+        assert_eq!(map.learning(), Some(SynthParam::Cutoff));
+
+        assert_eq!(map.handle(20), Some(SynthParam::Cutoff));
+        assert_eq!(map.learning(), None);
+        assert_eq!(map.control(SynthParam::Cutoff), Some(20));
+    }
+
+    #[test]
+    fn rebinding_replaces_previous() {
+        let mut map = CcMap::empty();
+        map.bind(20, SynthParam::Cutoff);
+        map.bind(21, SynthParam::Cutoff);
+        assert_eq!(map.param(20), None);
+        assert_eq!(map.param(21), Some(SynthParam::Cutoff));
+
+        map.bind(21, SynthParam::Resonance);
+        assert_eq!(map.control(SynthParam::Cutoff), None);
+        assert_eq!(map.bindings().count(), 1);
+
+        map.clear();
+        assert_eq!(map.bindings().count(), 0);
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let map = CcMap::default();
+        let restored = CcMap::from_bytes(&map.to_bytes());
+        assert_eq!(restored, map);
+
+        assert_eq!(CcBinding::from_bytes([EMPTY_SLOT, 0]), None);
+        assert_eq!(CcBinding::from_bytes([20, 0xFE]), None);
+    }
+}
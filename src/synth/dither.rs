@@ -0,0 +1,191 @@
+use micromath::F32Ext;
+
+use super::rng::Rng;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum DitherMode {
+    /// Plain conversion, lower bits are truncated by the output format
+    Off,
+    /// Triangular noise of ±1 LSB
+    #[default]
+    Tpdf,
+    /// TPDF with first-order error feedback, moves noise to high frequencies
+    NoiseShaped,
+}
+
+impl DitherMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DitherMode::Off => "Off",
+            DitherMode::Tpdf => "TPDF",
+            DitherMode::NoiseShaped => "Shaped",
+        }
+    }
+}
+
+impl core::fmt::Display for DitherMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Converts float samples to full-scale `i32` quantized to the output bit depth
+#[derive(Clone, Copy, Debug)]
+pub struct Dither {
+    mode: DitherMode,
+    bits: u32,
+    rng: Rng,
+    /// Quantization error of the previous sample per channel, for noise shaping
+    error: [f32; 2],
+}
+
+impl Dither {
+    pub fn new(bits: u32) -> Self {
+        Self {
+            mode: DitherMode::default(),
+            bits: bits.clamp(8, 32),
+            rng: Rng::new(0xD1_7E4),
+            error: [0.0; 2],
+        }
+    }
+
+    pub fn mode(&self) -> DitherMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DitherMode) {
+        self.mode = mode;
+        self.error = [0.0; 2];
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Set bit depth of the codec, 32 bits disables dithering as float has less precision
+    pub fn set_bits(&mut self, bits: u32) {
+        self.bits = bits.clamp(8, 32);
+        self.error = [0.0; 2];
+    }
+
+    /// Size of the output quantization step relative to full scale
+    fn step(&self) -> f32 {
+        1.0 / (1u32 << (self.bits - 1)) as f32
+    }
+
+    pub fn quantize(&mut self, (left, right): (f32, f32)) -> (i32, i32) {
+        (
+            self.quantize_sample(left, 0),
+            self.quantize_sample(right, 1),
+        )
+    }
+
+    fn quantize_sample(&mut self, sample: f32, channel: usize) -> i32 {
+        if self.mode == DitherMode::Off || self.bits >= 32 {
+            return (sample * i32::MAX as f32) as i32;
+        }
+
+        let step = self.step();
+        let input = match self.mode {
+            DitherMode::NoiseShaped => sample - self.error[channel],
+            _ => sample,
+        };
+
+        let noise = self.rng.next_f32() - self.rng.next_f32();
+        let max = (1i32 << (self.bits - 1)) - 1;
+        let quantized = ((input / step + noise).round() as i32).clamp(-max - 1, max);
+
+        self.error[channel] = quantized as f32 * step - input;
+
+        quantized << (32 - self.bits)
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new(32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNT: usize = 20_000;
+
+    fn dither(mode: DitherMode, bits: u32) -> Dither {
+        let mut dither = Dither::new(bits);
+        dither.set_mode(mode);
+        dither
+    }
+
+    /// Mean of the left output in LSBs for constant input
+    fn mean_lsb(dither: &mut Dither, input: f32) -> f32 {
+        let shift = 32 - dither.bits();
+        let sum: i64 = (0..COUNT)
+            .map(|_| (dither.quantize((input, input)).0 >> shift) as i64)
+            .sum();
+
+        sum as f32 / COUNT as f32
+    }
+
+    #[test]
+    fn off_is_plain_conversion() {
+        let mut dither = dither(DitherMode::Off, 16);
+        assert_eq!(
+            dither.quantize((0.5, -0.25)),
+            (
+                (0.5 * i32::MAX as f32) as i32,
+                (-0.25 * i32::MAX as f32) as i32
+            )
+        );
+    }
+
+    #[test]
+    fn output_is_on_the_grid() {
+        for bits in [16, 24] {
+            let mut dither = dither(DitherMode::NoiseShaped, bits);
+            let mask = (1i32 << (32 - bits)) - 1;
+
+            for n in 0..1_000 {
+                let (left, right) = dither.quantize((n as f32 * 1e-3, -1.0));
+                assert_eq!(left & mask, 0);
+                assert_eq!(right & mask, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn full_scale_does_not_wrap() {
+        let mut dither = dither(DitherMode::Tpdf, 16);
+        for _ in 0..1_000 {
+            let (left, right) = dither.quantize((1.0, -1.0));
+            assert!(left > i32::MAX / 2 && right < i32::MIN / 2);
+        }
+    }
+
+    #[test]
+    fn tpdf_preserves_sub_lsb_level() {
+        let step = Dither::new(16).step();
+        let input = 0.3 * step;
+
+        // Without dither signal below half LSB is lost entirely
+        assert_eq!(
+            dither(DitherMode::Off, 16).quantize((input, input)).0 >> 16,
+            0
+        );
+
+        let mean = mean_lsb(&mut dither(DitherMode::Tpdf, 16), input);
+        assert!((mean - 0.3).abs() < 0.05, "mean {}", mean);
+    }
+
+    #[test]
+    fn noise_shaping_cancels_error_at_dc() {
+        let step = Dither::new(16).step();
+        let input = 0.37 * step;
+
+        // Errors of consecutive samples cancel, so mean error is bounded by a couple of LSBs over the whole run
+        let mean = mean_lsb(&mut dither(DitherMode::NoiseShaped, 16), input);
+        assert!((mean - 0.37).abs() < 4.0 / COUNT as f32, "mean {}", mean);
+    }
+}
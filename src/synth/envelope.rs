@@ -0,0 +1,300 @@
+use crate::SAMPLE_RATE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum AdsrStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// ADSR envelope parameters.
+/// Times are rates, i.e. the time the envelope takes to travel the full 0..1 range, so the release from sustain level of 0.5 with 100ms release time takes 50ms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdsrParams {
+    attack_ms: f32,
+    decay_ms: f32,
+    sustain: f32,
+    release_ms: f32,
+
+    attack_step: f32,
+    decay_step: f32,
+    release_step: f32,
+}
+
+impl AdsrParams {
+    pub fn new(attack_ms: f32, decay_ms: f32, sustain: f32, release_ms: f32) -> Self {
+        let mut params = Self {
+            attack_ms: 0.0,
+            decay_ms: 0.0,
+            sustain: 0.0,
+            release_ms: 0.0,
+            attack_step: 1.0,
+            decay_step: 1.0,
+            release_step: 1.0,
+        };
+
+        params.set_attack(attack_ms);
+        params.set_decay(decay_ms);
+        params.set_sustain(sustain);
+        params.set_release(release_ms);
+
+        params
+    }
+
+    fn step(ms: f32) -> f32 {
+        if ms <= 0.0 {
+            1.0
+        } else {
+            (1_000.0 / (ms * SAMPLE_RATE as f32)).min(1.0)
+        }
+    }
+
+    pub fn attack(&self) -> f32 {
+        self.attack_ms
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay_ms
+    }
+
+    pub fn sustain(&self) -> f32 {
+        self.sustain
+    }
+
+    pub fn release(&self) -> f32 {
+        self.release_ms
+    }
+
+    pub fn set_attack(&mut self, ms: f32) {
+        self.attack_ms = ms.max(0.0);
+        self.attack_step = Self::step(self.attack_ms);
+    }
+
+    pub fn set_decay(&mut self, ms: f32) {
+        self.decay_ms = ms.max(0.0);
+        self.decay_step = Self::step(self.decay_ms);
+    }
+
+    pub fn set_sustain(&mut self, level: f32) {
+        self.sustain = level.clamp(0.0, 1.0);
+    }
+
+    pub fn set_release(&mut self, ms: f32) {
+        self.release_ms = ms.max(0.0);
+        self.release_step = Self::step(self.release_ms);
+    }
+}
+
+impl Default for AdsrParams {
+    fn default() -> Self {
+        Self::new(5.0, 100.0, 0.8, 200.0)
+    }
+}
+
+/// Linear ADSR envelope generator, outputs level in 0..=1 range at `SAMPLE_RATE`.
+pub struct Adsr {
+    stage: AdsrStage,
+    level: f32,
+}
+
+impl Adsr {
+    pub fn new() -> Self {
+        Self {
+            stage: AdsrStage::Idle,
+            level: 0.0,
+        }
+    }
+
+    /// Start the attack stage. The envelope continues from its current level, so retriggering a releasing voice does not click.
+    pub fn gate_on(&mut self) {
+        self.stage = AdsrStage::Attack;
+    }
+
+    pub fn gate_off(&mut self) {
+        if self.stage != AdsrStage::Idle {
+            self.stage = AdsrStage::Release;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stage = AdsrStage::Idle;
+        self.level = 0.0;
+    }
+
+    pub fn stage(&self) -> AdsrStage {
+        self.stage
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != AdsrStage::Idle
+    }
+
+    pub fn is_released(&self) -> bool {
+        matches!(self.stage, AdsrStage::Release | AdsrStage::Idle)
+    }
+
+    pub fn next_sample(&mut self, params: &AdsrParams) -> f32 {
+        match self.stage {
+            AdsrStage::Idle => {}
+            AdsrStage::Attack => {
+                self.level += params.attack_step;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = AdsrStage::Decay;
+                }
+            }
+            AdsrStage::Decay => {
+                self.level -= params.decay_step;
+                if self.level <= params.sustain {
+                    self.level = params.sustain;
+                    self.stage = AdsrStage::Sustain;
+                }
+            }
+            AdsrStage::Sustain => {
+                // Follow sustain level changes while the note is held
+                self.level = params.sustain;
+            }
+            AdsrStage::Release => {
+                self.level -= params.release_step;
+                if self.level <= 0.0 {
+                    self.reset();
+                }
+            }
+        }
+
+        self.level
+    }
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(ms: f32) -> usize {
+        (ms * SAMPLE_RATE as f32 / 1_000.0) as usize
+    }
+
+    fn run(env: &mut Adsr, params: &AdsrParams, count: usize) -> f32 {
+        let mut level = env.level();
+        for _ in 0..count {
+            level = env.next_sample(params);
+        }
+        level
+    }
+
+    fn samples_until(env: &mut Adsr, params: &AdsrParams, stage: AdsrStage) -> usize {
+        let mut count = 0;
+        while env.stage() != stage {
+            env.next_sample(params);
+            count += 1;
+            assert!(count < 1_000_000, "Stage {:?} is never reached", stage);
+        }
+        count
+    }
+
+    #[test]
+    fn idle_is_silent() {
+        let params = AdsrParams::default();
+        let mut env = Adsr::new();
+
+        assert_eq!(run(&mut env, &params, 100), 0.0);
+        assert!(!env.is_active());
+    }
+
+    #[test]
+    fn stage_transitions() {
+        let params = AdsrParams::new(10.0, 20.0, 0.5, 40.0);
+        let mut env = Adsr::new();
+
+        env.gate_on();
+        assert_eq!(env.stage(), AdsrStage::Attack);
+
+        let attack = samples_until(&mut env, &params, AdsrStage::Decay);
+        assert!(attack.abs_diff(samples(10.0)) <= 1);
+        assert_eq!(env.level(), 1.0);
+
+        // Decay from 1.0 to 0.5 takes half of the decay time
+        let decay = samples_until(&mut env, &params, AdsrStage::Sustain);
+        assert!(decay.abs_diff(samples(10.0)) <= 1);
+        assert_eq!(env.level(), 0.5);
+
+        run(&mut env, &params, 10_000);
+        assert_eq!(env.stage(), AdsrStage::Sustain);
+        assert_eq!(env.level(), 0.5);
+
+        env.gate_off();
+        assert_eq!(env.stage(), AdsrStage::Release);
+        let release = samples_until(&mut env, &params, AdsrStage::Idle);
+        assert!(release.abs_diff(samples(20.0)) <= 1);
+        assert_eq!(env.level(), 0.0);
+        assert!(!env.is_active());
+    }
+
+    #[test]
+    fn attack_is_linear() {
+        let params = AdsrParams::new(10.0, 0.0, 1.0, 0.0);
+        let mut env = Adsr::new();
+
+        env.gate_on();
+        let quarter = run(&mut env, &params, samples(2.5));
+        let half = run(&mut env, &params, samples(2.5));
+
+        assert!((quarter - 0.25).abs() < 1e-3);
+        assert!((half - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn release_during_attack() {
+        let params = AdsrParams::new(10.0, 10.0, 1.0, 10.0);
+        let mut env = Adsr::new();
+
+        env.gate_on();
+        let level = run(&mut env, &params, samples(5.0));
+        env.gate_off();
+
+        let next = env.next_sample(&params);
+        assert_eq!(env.stage(), AdsrStage::Release);
+        assert!(next < level);
+    }
+
+    #[test]
+    fn retrigger_continues_from_current_level() {
+        let params = AdsrParams::new(10.0, 10.0, 0.8, 100.0);
+        let mut env = Adsr::new();
+
+        env.gate_on();
+        run(&mut env, &params, samples(50.0));
+        env.gate_off();
+        let level = run(&mut env, &params, samples(10.0));
+
+        env.gate_on();
+        let next = env.next_sample(&params);
+        assert!(next > level);
+        assert!(next - level < 0.01);
+    }
+
+    #[test]
+    fn zero_times_are_instant() {
+        let params = AdsrParams::new(0.0, 0.0, 0.3, 0.0);
+        let mut env = Adsr::new();
+
+        env.gate_on();
+        assert_eq!(env.next_sample(&params), 1.0);
+        assert_eq!(env.next_sample(&params), 0.3);
+        env.gate_off();
+        assert_eq!(env.next_sample(&params), 0.0);
+        assert!(!env.is_active());
+    }
+}
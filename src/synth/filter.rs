@@ -0,0 +1,296 @@
+use core::f32::consts::PI;
+
+use micromath::F32Ext;
+
+use crate::SAMPLE_RATE;
+
+pub const MIN_CUTOFF: f32 = 20.0;
+pub const MAX_CUTOFF: f32 = SAMPLE_RATE as f32 * 0.45;
+/// Resonance is limited below self-oscillation
+const MAX_RESONANCE: f32 = 0.99;
+/// Key tracking is relative to C4
+const KEY_TRACK_CENTER: f32 = 60.0;
+/// Cutoff shift by filter envelope at full amount
+pub const ENV_AMOUNT_OCTAVES: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum FilterMode {
+    #[default]
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+impl FilterMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterMode::LowPass => "LP",
+            FilterMode::HighPass => "HP",
+            FilterMode::BandPass => "BP",
+            FilterMode::Notch => "Notch",
+        }
+    }
+}
+
+impl core::fmt::Display for FilterMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterParams {
+    pub mode: FilterMode,
+    /// Cutoff frequency in Hz
+    pub cutoff: f32,
+    /// Resonance in 0..1 range
+    pub resonance: f32,
+    /// How much cutoff follows the played note, 1.0 moves cutoff by an octave per octave
+    pub key_tracking: f32,
+    /// Bipolar filter envelope amount in -1..1 range, full amount shifts cutoff by `ENV_AMOUNT_OCTAVES`
+    pub env_amount: f32,
+    /// How much velocity scales envelope amount, 0.0 ignores velocity
+    pub env_velocity: f32,
+}
+
+impl FilterParams {
+    /// Cutoff frequency for fractional MIDI pitch with key tracking applied
+    pub fn tracked_cutoff(&self, pitch: f32) -> f32 {
+        self.cutoff * 2f32.powf(self.key_octaves(pitch))
+    }
+
+    fn key_octaves(&self, pitch: f32) -> f32 {
+        (pitch - KEY_TRACK_CENTER) / 12.0 * self.key_tracking
+    }
+
+    /// Envelope shift of cutoff in octaves for envelope level and velocity gain both in 0..1 range
+    pub fn env_octaves(&self, env_level: f32, velocity: f32) -> f32 {
+        let amount = self.env_amount * (1.0 - self.env_velocity + self.env_velocity * velocity);
+
+        env_level * amount * ENV_AMOUNT_OCTAVES
+    }
+
+    /// Cutoff frequency with key tracking, filter envelope and additional `mod_octaves` shift applied
+    pub fn modulated_cutoff(
+        &self,
+        pitch: f32,
+        env_level: f32,
+        velocity: f32,
+        mod_octaves: f32,
+    ) -> f32 {
+        self.cutoff
+            * 2f32
+                .powf(self.key_octaves(pitch) + self.env_octaves(env_level, velocity) + mod_octaves)
+    }
+}
+
+impl Default for FilterParams {
+    fn default() -> Self {
+        Self {
+            mode: FilterMode::LowPass,
+            cutoff: MAX_CUTOFF,
+            resonance: 0.0,
+            key_tracking: 0.0,
+            env_amount: 0.0,
+            env_velocity: 0.0,
+        }
+    }
+}
+
+/// Multimode state-variable filter (trapezoidal integration by A. Simper).
+/// Stable at any cutoff up to `MAX_CUTOFF`, coefficients are meant to be updated at control rate.
+#[derive(Clone, Copy, Debug)]
+pub struct Svf {
+    ic1eq: f32,
+    ic2eq: f32,
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+}
+
+impl Svf {
+    pub fn new() -> Self {
+        let mut svf = Self {
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+            k: 2.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+        };
+        svf.set(MAX_CUTOFF, 0.0);
+        svf
+    }
+
+    /// Set cutoff in Hz and resonance in 0..1 range
+    pub fn set(&mut self, cutoff: f32, resonance: f32) {
+        let cutoff = cutoff.clamp(MIN_CUTOFF, MAX_CUTOFF);
+        let g = (PI * cutoff / SAMPLE_RATE as f32).tan();
+
+        self.k = 2.0 - 2.0 * resonance.clamp(0.0, MAX_RESONANCE);
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    pub fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+
+    pub fn process(&mut self, input: f32, mode: FilterMode) -> f32 {
+        let v3 = input - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        match mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => input - self.k * v1 - v2,
+            // Normalized to unity gain at cutoff
+            FilterMode::BandPass => self.k * v1,
+            FilterMode::Notch => input - self.k * v1,
+        }
+    }
+}
+
+impl Default for Svf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::TAU;
+
+    use super::*;
+
+    /// Steady-state gain of filter for sine input at `freq`
+    fn gain(mode: FilterMode, cutoff: f32, resonance: f32, freq: f32) -> f32 {
+        let mut svf = Svf::new();
+        svf.set(cutoff, resonance);
+
+        let sine = |n: u32| (TAU * freq * n as f32 / SAMPLE_RATE as f32).sin();
+
+        // Let the filter settle
+        let settle = SAMPLE_RATE / 2;
+        for n in 0..settle {
+            svf.process(sine(n), mode);
+        }
+
+        let (input, output) = (settle..settle + SAMPLE_RATE / 4)
+            .map(|n| {
+                let input = sine(n);
+                (input * input, svf.process(input, mode).powi(2))
+            })
+            .fold((0.0, 0.0), |(i, o), (si, so)| (i + si, o + so));
+
+        (output / input).sqrt()
+    }
+
+    #[test]
+    fn low_pass() {
+        assert!((gain(FilterMode::LowPass, 1_000.0, 0.0, 100.0) - 1.0).abs() < 0.02);
+        // 12dB per octave
+        assert!(gain(FilterMode::LowPass, 1_000.0, 0.0, 10_000.0) < 0.02);
+    }
+
+    #[test]
+    fn high_pass() {
+        assert!(gain(FilterMode::HighPass, 1_000.0, 0.0, 100.0) < 0.02);
+        assert!((gain(FilterMode::HighPass, 1_000.0, 0.0, 10_000.0) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn band_pass() {
+        assert!((gain(FilterMode::BandPass, 1_000.0, 0.8, 1_000.0) - 1.0).abs() < 0.02);
+        assert!(gain(FilterMode::BandPass, 1_000.0, 0.8, 100.0) < 0.1);
+        assert!(gain(FilterMode::BandPass, 1_000.0, 0.8, 10_000.0) < 0.1);
+    }
+
+    #[test]
+    fn notch() {
+        assert!(gain(FilterMode::Notch, 1_000.0, 0.0, 1_000.0) < 0.01);
+        assert!((gain(FilterMode::Notch, 1_000.0, 0.0, 100.0) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn resonance_peak() {
+        let flat = gain(FilterMode::LowPass, 1_000.0, 0.0, 1_000.0);
+        let resonant = gain(FilterMode::LowPass, 1_000.0, 0.9, 1_000.0);
+
+        assert!(resonant > 4.0 * flat);
+    }
+
+    #[test]
+    fn stable_across_range() {
+        for cutoff in [MIN_CUTOFF, 200.0, 2_000.0, 15_000.0, MAX_CUTOFF, 30_000.0] {
+            for freq in [30.0, 1_000.0, 20_000.0] {
+                let gain = gain(FilterMode::LowPass, cutoff, 1.0, freq);
+                assert!(gain.is_finite() && gain < 100.0, "cutoff {}Hz", cutoff);
+            }
+        }
+    }
+
+    #[test]
+    fn key_tracking() {
+        let params = FilterParams {
+            cutoff: 1_000.0,
+            key_tracking: 1.0,
+            ..Default::default()
+        };
+
+        assert!((params.tracked_cutoff(60.0) - 1_000.0).abs() < 1e-3);
+        assert!((params.tracked_cutoff(72.0) - 2_000.0).abs() < 1e-2);
+
+        let half = FilterParams {
+            key_tracking: 0.5,
+            ..params
+        };
+        assert!((half.tracked_cutoff(84.0) - 2_000.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn envelope_amount() {
+        let params = FilterParams {
+            cutoff: 1_000.0,
+            env_amount: 0.25,
+            ..Default::default()
+        };
+
+        assert!((params.modulated_cutoff(60.0, 0.0, 1.0, 0.0) - 1_000.0).abs() < 1e-3);
+        // Quarter of 8 octaves
+        assert!((params.modulated_cutoff(60.0, 1.0, 1.0, 0.0) - 4_000.0).abs() < 0.1);
+
+        let negative = FilterParams {
+            env_amount: -0.125,
+            ..params
+        };
+        assert!((negative.modulated_cutoff(60.0, 1.0, 1.0, 0.0) - 500.0).abs() < 0.1);
+        assert!((negative.modulated_cutoff(60.0, 1.0, 1.0, 1.0) - 1_000.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn envelope_velocity_scaling() {
+        let params = FilterParams {
+            env_amount: 0.5,
+            env_velocity: 1.0,
+            ..Default::default()
+        };
+
+        assert_eq!(params.env_octaves(1.0, 0.0), 0.0);
+        assert!((params.env_octaves(1.0, 0.5) - 2.0).abs() < 1e-6);
+        assert!((params.env_octaves(1.0, 1.0) - 4.0).abs() < 1e-6);
+
+        let half = FilterParams {
+            env_velocity: 0.5,
+            ..params
+        };
+        assert!((half.env_octaves(1.0, 0.0) - 2.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,183 @@
+use micromath::F32Ext;
+
+use crate::SAMPLE_RATE;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum GlideMode {
+    /// Every glide takes `GlideParams::time` regardless of the interval
+    #[default]
+    ConstantTime,
+    /// Glide takes `GlideParams::time` per octave
+    ConstantRate,
+}
+
+impl GlideMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GlideMode::ConstantTime => "Time",
+            GlideMode::ConstantRate => "Rate",
+        }
+    }
+}
+
+impl core::fmt::Display for GlideMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Portamento, pitch glides linearly in semitones which is exponential in frequency
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GlideParams {
+    pub mode: GlideMode,
+    /// Glide time in ms, 0 disables portamento
+    pub time: f32,
+}
+
+impl GlideParams {
+    pub fn is_enabled(&self) -> bool {
+        self.time > 0.0
+    }
+
+    /// Pitch change in semitones per update of `period` samples to glide from `from` to `to` pitch
+    pub fn step(&self, from: f32, to: f32, period: usize) -> f32 {
+        let updates_per_ms = SAMPLE_RATE as f32 / 1_000.0 / period as f32;
+
+        let distance = to - from;
+        let semitones_per_ms = match self.mode {
+            GlideMode::ConstantTime => distance.abs() / self.time,
+            GlideMode::ConstantRate => 12.0 / self.time,
+        };
+
+        (semitones_per_ms / updates_per_ms).copysign(distance)
+    }
+}
+
+/// Current pitch of a voice moving towards the played note
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Glide {
+    pitch: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Glide {
+    /// Jump to pitch immediately
+    pub fn set(&mut self, pitch: f32) {
+        self.pitch = pitch;
+        self.target = pitch;
+        self.step = 0.0;
+    }
+
+    /// Start gliding from the current pitch
+    pub fn glide_to(&mut self, pitch: f32, params: &GlideParams, period: usize) {
+        if !params.is_enabled() {
+            return self.set(pitch);
+        }
+
+        self.target = pitch;
+        self.step = params.step(self.pitch, pitch, period);
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn is_gliding(&self) -> bool {
+        self.pitch != self.target
+    }
+
+    /// Move by a single step, returns the new pitch
+    pub fn advance(&mut self) -> f32 {
+        if self.is_gliding() {
+            self.pitch += self.step;
+            if (self.step > 0.0 && self.pitch >= self.target)
+                || (self.step <= 0.0 && self.pitch <= self.target)
+            {
+                self.pitch = self.target;
+            }
+        }
+        self.pitch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: usize = 32;
+
+    /// Count of steps to reach the target
+    fn steps(params: &GlideParams, from: f32, to: f32) -> usize {
+        let mut glide = Glide::default();
+        glide.set(from);
+        glide.glide_to(to, params, PERIOD);
+
+        let mut steps = 0;
+        while glide.is_gliding() {
+            let last = glide.pitch();
+            let pitch = glide.advance();
+            assert!((pitch - last).abs() <= (to - from).abs());
+            assert!(pitch >= from.min(to) && pitch <= from.max(to));
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn constant_time() {
+        let params = GlideParams {
+            mode: GlideMode::ConstantTime,
+            time: 100.0,
+        };
+        // 100ms of 32-sample updates
+        let expected = SAMPLE_RATE as usize / 10 / PERIOD;
+
+        assert!(steps(&params, 60.0, 72.0).abs_diff(expected) <= 1);
+        assert!(steps(&params, 60.0, 36.0).abs_diff(expected) <= 1);
+        assert!(steps(&params, 60.0, 61.0).abs_diff(expected) <= 1);
+    }
+
+    #[test]
+    fn constant_rate() {
+        let params = GlideParams {
+            mode: GlideMode::ConstantRate,
+            time: 100.0,
+        };
+        let octave = SAMPLE_RATE as usize / 10 / PERIOD;
+
+        assert!(steps(&params, 60.0, 72.0).abs_diff(octave) <= 1);
+        assert!(steps(&params, 72.0, 48.0).abs_diff(octave * 2) <= 1);
+        assert!(steps(&params, 60.0, 66.0).abs_diff(octave / 2) <= 1);
+    }
+
+    #[test]
+    fn disabled_jumps() {
+        let mut glide = Glide::default();
+        glide.set(60.0);
+        glide.glide_to(67.0, &GlideParams::default(), PERIOD);
+
+        assert!(!glide.is_gliding());
+        assert_eq!(glide.pitch(), 67.0);
+    }
+
+    #[test]
+    fn retarget_mid_glide() {
+        let params = GlideParams {
+            mode: GlideMode::ConstantTime,
+            time: 10.0,
+        };
+        let mut glide = Glide::default();
+        glide.set(60.0);
+        glide.glide_to(72.0, &params, PERIOD);
+        for _ in 0..5 {
+            glide.advance();
+        }
+
+        let from = glide.pitch();
+        assert!(from > 60.0 && from < 72.0);
+
+        glide.glide_to(48.0, &params, PERIOD);
+        assert!(glide.advance() < from);
+    }
+}
@@ -0,0 +1,343 @@
+use core::f32::consts::{PI, TAU};
+
+use micromath::F32Ext;
+
+use super::rng::Rng;
+use crate::SAMPLE_RATE;
+
+pub const DEFAULT_TEMPO: f32 = 120.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    /// New random value every cycle
+    SampleHold,
+    /// Random values smoothly interpolated over a cycle
+    SmoothRandom,
+}
+
+impl LfoShape {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "Sine",
+            LfoShape::Triangle => "Tri",
+            LfoShape::Saw => "Saw",
+            LfoShape::Square => "Square",
+            LfoShape::SampleHold => "S&H",
+            LfoShape::SmoothRandom => "Smooth",
+        }
+    }
+}
+
+impl core::fmt::Display for LfoShape {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Note length of one LFO cycle when synced to tempo
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum SyncDivision {
+    Bars(u8),
+    Half,
+    Quarter,
+    DottedEighth,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+}
+
+impl SyncDivision {
+    /// Length in quarter-note beats
+    pub fn beats(&self) -> f32 {
+        match *self {
+            SyncDivision::Bars(bars) => bars.max(1) as f32 * 4.0,
+            SyncDivision::Half => 2.0,
+            SyncDivision::Quarter => 1.0,
+            SyncDivision::DottedEighth => 0.75,
+            SyncDivision::Eighth => 0.5,
+            SyncDivision::EighthTriplet => 1.0 / 3.0,
+            SyncDivision::Sixteenth => 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum LfoRate {
+    Hz(f32),
+    Sync(SyncDivision),
+}
+
+impl LfoRate {
+    pub fn freq(&self, tempo: f32) -> f32 {
+        match self {
+            LfoRate::Hz(freq) => *freq,
+            LfoRate::Sync(division) => tempo / 60.0 / division.beats(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LfoParams {
+    pub shape: LfoShape,
+    pub rate: LfoRate,
+    /// Restart the cycle on note-on
+    pub retrigger: bool,
+    /// Fade-in time after note-on in ms
+    pub delay_ms: f32,
+    /// Vibrato depth in semitones
+    pub pitch_depth: f32,
+    /// Tremolo depth in 0..1 range
+    pub amp_depth: f32,
+    /// Filter cutoff modulation depth in octaves
+    pub cutoff_depth: f32,
+}
+
+impl Default for LfoParams {
+    fn default() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            rate: LfoRate::Hz(5.0),
+            retrigger: false,
+            delay_ms: 0.0,
+            pitch_depth: 0.0,
+            amp_depth: 0.0,
+            cutoff_depth: 0.0,
+        }
+    }
+}
+
+/// Low-frequency oscillator, outputs bipolar -1..1 value. Meant to be advanced at control rate.
+#[derive(Clone, Copy, Debug)]
+pub struct Lfo {
+    phase: f32,
+    fade: f32,
+    random: (f32, f32),
+    rng: Rng,
+    value: f32,
+}
+
+impl Lfo {
+    pub fn new(seed: u32) -> Self {
+        let mut rng = Rng::new(seed);
+        let random = (rng.next_bipolar(), rng.next_bipolar());
+
+        Self {
+            phase: 0.0,
+            fade: 1.0,
+            random,
+            rng,
+            value: 0.0,
+        }
+    }
+
+    /// Note-on, restarts the cycle if `retrigger` is set and the fade-in
+    pub fn trigger(&mut self, params: &LfoParams) {
+        if params.retrigger {
+            self.phase = 0.0;
+        }
+        self.fade = 0.0;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    /// Advance LFO by `samples` count of samples, `rate_mod` is an additional rate shift in octaves
+    pub fn advance(
+        &mut self,
+        params: &LfoParams,
+        tempo: f32,
+        rate_mod: f32,
+        samples: usize,
+    ) -> f32 {
+        let mut freq = params.rate.freq(tempo);
+        if rate_mod != 0.0 {
+            freq *= 2f32.powf(rate_mod);
+        }
+
+        self.phase += freq * samples as f32 / SAMPLE_RATE as f32;
+        if self.phase >= 1.0 {
+            self.phase -= (self.phase as u32) as f32;
+            self.random = (self.random.1, self.rng.next_bipolar());
+        }
+
+        self.fade = if params.delay_ms <= 0.0 {
+            1.0
+        } else {
+            (self.fade + samples as f32 * 1_000.0 / (params.delay_ms * SAMPLE_RATE as f32)).min(1.0)
+        };
+
+        self.value = self.shape_value(params.shape) * self.fade;
+        self.value
+    }
+
+    fn shape_value(&self, shape: LfoShape) -> f32 {
+        let phase = self.phase;
+
+        match shape {
+            LfoShape::Sine => (phase * TAU).sin(),
+            LfoShape::Triangle => {
+                if phase < 0.25 {
+                    4.0 * phase
+                } else if phase < 0.75 {
+                    2.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 4.0
+                }
+            }
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::SampleHold => self.random.1,
+            LfoShape::SmoothRandom => {
+                // Cosine interpolation between previous and current random values
+                let t = 0.5 - 0.5 * (phase * PI).cos();
+                self.random.0 + (self.random.1 - self.random.0) * t
+            }
+        }
+    }
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: usize = 32;
+
+    fn params(shape: LfoShape, rate: LfoRate) -> LfoParams {
+        LfoParams {
+            shape,
+            rate,
+            ..Default::default()
+        }
+    }
+
+    /// Count of cycles LFO makes in one second
+    fn cycles(params: &LfoParams, tempo: f32) -> u32 {
+        let mut lfo = Lfo::default();
+        let mut cycles = 0;
+        let mut last_phase = lfo.phase();
+
+        // One extra step so that the last cycle is not lost to rounding
+        for _ in 0..=SAMPLE_RATE as usize / STEP {
+            lfo.advance(params, tempo, 0.0, STEP);
+            if lfo.phase() < last_phase {
+                cycles += 1;
+            }
+            last_phase = lfo.phase();
+        }
+
+        cycles
+    }
+
+    #[test]
+    fn rate_in_hz() {
+        assert_eq!(cycles(&params(LfoShape::Sine, LfoRate::Hz(5.0)), 0.0), 5);
+    }
+
+    #[test]
+    fn tempo_sync() {
+        // 120BPM is 2 quarter notes per second
+        let quarter = params(LfoShape::Sine, LfoRate::Sync(SyncDivision::Quarter));
+        assert_eq!(cycles(&quarter, 120.0), 2);
+        assert_eq!(cycles(&quarter, 180.0), 3);
+
+        let sixteenth = params(LfoShape::Sine, LfoRate::Sync(SyncDivision::Sixteenth));
+        assert_eq!(cycles(&sixteenth, 120.0), 8);
+    }
+
+    #[test]
+    fn rate_modulation() {
+        let params = params(LfoShape::Sine, LfoRate::Hz(2.0));
+        let mut lfo = Lfo::default();
+        lfo.advance(&params, 0.0, 1.0, SAMPLE_RATE as usize / 16);
+
+        // One octave up doubles the rate
+        assert!((lfo.phase() - 0.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shapes_are_bipolar() {
+        for shape in [
+            LfoShape::Sine,
+            LfoShape::Triangle,
+            LfoShape::Saw,
+            LfoShape::Square,
+            LfoShape::SampleHold,
+            LfoShape::SmoothRandom,
+        ] {
+            let params = params(shape, LfoRate::Hz(3.0));
+            let mut lfo = Lfo::new(42);
+            let (min, max) = (0..SAMPLE_RATE as usize / STEP)
+                .map(|_| lfo.advance(&params, 0.0, 0.0, STEP))
+                .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                    (min.min(v), max.max(v))
+                });
+
+            assert!(min >= -1.0 && max <= 1.0, "{:?} out of range", shape);
+            assert!(max - min > 0.5, "{:?} is too flat", shape);
+        }
+    }
+
+    #[test]
+    fn smooth_random_is_continuous() {
+        let params = params(LfoShape::SmoothRandom, LfoRate::Hz(10.0));
+        let mut lfo = Lfo::new(7);
+        let mut last = lfo.advance(&params, 0.0, 0.0, STEP);
+
+        for _ in 0..SAMPLE_RATE as usize / STEP {
+            let value = lfo.advance(&params, 0.0, 0.0, STEP);
+            assert!((value - last).abs() < 0.1);
+            last = value;
+        }
+    }
+
+    #[test]
+    fn retrigger_resets_phase() {
+        let mut params = params(LfoShape::Saw, LfoRate::Hz(1.0));
+        let mut lfo = Lfo::default();
+        lfo.advance(&params, 0.0, 0.0, SAMPLE_RATE as usize / 2);
+
+        lfo.trigger(&params);
+        assert!(lfo.phase() > 0.4);
+
+        params.retrigger = true;
+        lfo.trigger(&params);
+        assert_eq!(lfo.phase(), 0.0);
+    }
+
+    #[test]
+    fn delay_fades_in() {
+        let params = LfoParams {
+            delay_ms: 100.0,
+            ..params(LfoShape::Square, LfoRate::Hz(1.0))
+        };
+        let mut lfo = Lfo::default();
+        lfo.trigger(&params);
+
+        let half = SAMPLE_RATE as usize / 20;
+        assert!((lfo.advance(&params, 0.0, 0.0, half) - 0.5).abs() < 1e-3);
+        assert!((lfo.advance(&params, 0.0, 0.0, half) - 1.0).abs() < 1e-3);
+        assert_eq!(lfo.advance(&params, 0.0, 0.0, half), 1.0);
+    }
+}
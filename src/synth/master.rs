@@ -0,0 +1,322 @@
+use micromath::F32Ext;
+
+use super::{smooth::Smoother, voice::CONTROL_PERIOD};
+
+/// Time constant of master gain smoothing in ms
+const GAIN_SMOOTHING_MS: f32 = 10.0;
+/// Limiter ceiling, just below full scale
+pub const LIMITER_THRESHOLD: f32 = 0.98;
+/// Limiter gain recovery time constant in ms
+const LIMITER_RELEASE_MS: f32 = 80.0;
+/// Time constant of polyphony used for headroom falling back after voices finish, in ms.
+/// Long enough that released notes don't make held ones pump.
+const POLYPHONY_RELEASE_MS: f32 = 2_000.0;
+/// Saturator is linear below this level
+const SATURATION_KNEE: f32 = 0.5;
+/// Gain of muted output considered silent, -100 dB
+const SILENCE_GAIN: f32 = 1e-5;
+
+/// Soft saturation, linear up to `SATURATION_KNEE` and asymptotically approaching 1.0
+pub fn saturate(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= SATURATION_KNEE {
+        return sample;
+    }
+
+    let over = (magnitude - SATURATION_KNEE) / (1.0 - SATURATION_KNEE);
+    let saturated = SATURATION_KNEE + (1.0 - SATURATION_KNEE) * over / (1.0 + over);
+
+    saturated.copysign(sample)
+}
+
+/// Output stage applied to the sum of voices before quantization:
+/// smoothed volume with polyphony headroom, soft saturator and peak limiter.
+#[derive(Clone, Copy, Debug)]
+pub struct Master {
+    volume: f32,
+    /// Left/right balance in -1..1 range, attenuates the opposite channel
+    balance: f32,
+    /// Scale down by square root of active voices count
+    headroom: bool,
+    saturation: bool,
+    /// Output ramps down to silence, e.g. while flash writes stall rendering
+    muted: bool,
+    /// Recent maximum of active voices count, rises instantly and falls slowly
+    polyphony: Smoother,
+    /// Smoothed left and right gains
+    gain: (Smoother, Smoother),
+    /// Gain reduction jumping down on peaks and recovering towards 1.0
+    limiter: Smoother,
+}
+
+impl Master {
+    pub fn new() -> Self {
+        Self {
+            volume: 1.0,
+            balance: 0.0,
+            headroom: true,
+            saturation: true,
+            muted: false,
+            polyphony: Smoother::new(POLYPHONY_RELEASE_MS, CONTROL_PERIOD),
+            gain: (
+                Self::unity(GAIN_SMOOTHING_MS),
+                Self::unity(GAIN_SMOOTHING_MS),
+            ),
+            limiter: Self::unity(LIMITER_RELEASE_MS),
+        }
+    }
+
+    /// Per-sample smoother starting at unity gain
+    fn unity(time_ms: f32) -> Smoother {
+        let mut smoother = Smoother::new(time_ms, 1);
+        smoother.reset(1.0);
+        smoother
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Set master volume in 0..1 range, change is smoothed
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn balance(&self) -> f32 {
+        self.balance
+    }
+
+    pub fn set_balance(&mut self, balance: f32) {
+        self.balance = balance.clamp(-1.0, 1.0);
+    }
+
+    pub fn headroom(&self) -> bool {
+        self.headroom
+    }
+
+    pub fn set_headroom(&mut self, headroom: bool) {
+        self.headroom = headroom;
+    }
+
+    pub fn saturation(&self) -> bool {
+        self.saturation
+    }
+
+    pub fn set_saturation(&mut self, saturation: bool) {
+        self.saturation = saturation;
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Mute or unmute output, gain ramps as with volume changes
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Muted and gain has ramped down, so rendered blocks are silent
+    pub fn is_silent(&self) -> bool {
+        self.muted && self.gain.0.value() < SILENCE_GAIN && self.gain.1.value() < SILENCE_GAIN
+    }
+
+    /// Count of sounding voices used for headroom scaling, meant to be updated at control rate
+    pub fn set_voices(&mut self, voices: usize) {
+        let voices = voices as f32;
+        if voices >= self.polyphony.value() {
+            self.polyphony.reset(voices);
+        } else {
+            self.polyphony.set_target(voices);
+            self.polyphony.advance();
+        }
+    }
+
+    /// Current limiter gain reduction, 1.0 is no reduction
+    pub fn limiter_gain(&self) -> f32 {
+        self.limiter.value()
+    }
+
+    fn target_gain(&self) -> (f32, f32) {
+        if self.muted {
+            return (0.0, 0.0);
+        }
+
+        let polyphony = self.polyphony.value();
+        let gain = if self.headroom && polyphony > 1.0 {
+            self.volume / polyphony.sqrt()
+        } else {
+            self.volume
+        };
+
+        (
+            gain * (1.0 - self.balance).min(1.0),
+            gain * (1.0 + self.balance).min(1.0),
+        )
+    }
+
+    /// Process stereo frame, output never exceeds `LIMITER_THRESHOLD`
+    pub fn process(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        let target = self.target_gain();
+        self.gain.0.set_target(target.0);
+        self.gain.1.set_target(target.1);
+
+        let (mut left, mut right) = (left * self.gain.0.advance(), right * self.gain.1.advance());
+        if self.saturation {
+            left = saturate(left);
+            right = saturate(right);
+        }
+
+        // Instant attack, exponential release
+        let peak = left.abs().max(right.abs());
+        let mut limiter_gain = self.limiter.advance();
+        if peak * limiter_gain > LIMITER_THRESHOLD {
+            limiter_gain = LIMITER_THRESHOLD / peak;
+            self.limiter.reset(limiter_gain);
+            self.limiter.set_target(1.0);
+        }
+
+        (left * limiter_gain, right * limiter_gain)
+    }
+}
+
+impl Default for Master {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+
+    #[test]
+    fn saturator_is_bounded_and_monotonic() {
+        assert_eq!(saturate(0.3), 0.3);
+        assert_eq!(saturate(-0.5), -0.5);
+
+        let mut last = 0.0;
+        for i in 1..1_000 {
+            let saturated = saturate(i as f32 * 0.01);
+            assert!(saturated > last && saturated < 1.0);
+            assert_eq!(saturate(-(i as f32) * 0.01), -saturated);
+            last = saturated;
+        }
+    }
+
+    #[test]
+    fn limiter_holds_ceiling() {
+        let mut master = Master::new();
+        master.set_saturation(false);
+
+        for n in 0..SAMPLE_RATE {
+            let input = if n % 100 < 50 { 10.0 } else { -3.0 };
+            let (left, right) = master.process((input, -input));
+            assert!(left.abs() <= LIMITER_THRESHOLD + 1e-6);
+            assert!(right.abs() <= LIMITER_THRESHOLD + 1e-6);
+        }
+    }
+
+    #[test]
+    fn limiter_recovers() {
+        let mut master = Master::new();
+        master.set_saturation(false);
+        master.process((5.0, 5.0));
+        assert!(master.limiter_gain() < 0.2);
+
+        for _ in 0..SAMPLE_RATE {
+            master.process((0.1, 0.1));
+        }
+        assert!(master.limiter_gain() > 0.999);
+    }
+
+    #[test]
+    fn volume_is_smoothed() {
+        let mut master = Master::new();
+        master.set_volume(0.0);
+
+        let (first, _) = master.process((0.4, 0.4));
+        assert!(first > 0.3);
+
+        for _ in 0..SAMPLE_RATE / 10 {
+            master.process((0.4, 0.4));
+        }
+        assert!(master.process((0.4, 0.4)).0 < 1e-3);
+    }
+
+    #[test]
+    fn balance_attenuates_opposite_side() {
+        let mut master = Master::new();
+        master.set_balance(0.5);
+
+        for _ in 0..SAMPLE_RATE / 10 {
+            master.process((0.4, 0.4));
+        }
+        let (left, right) = master.process((0.4, 0.4));
+        assert!((left - 0.2).abs() < 1e-3);
+        assert!((right - 0.4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn headroom_scales_by_voices() {
+        let mut master = Master::new();
+        master.set_saturation(false);
+        master.set_voices(4);
+
+        for _ in 0..SAMPLE_RATE / 10 {
+            master.process((0.4, 0.4));
+        }
+        assert!((master.process((0.4, 0.4)).0 - 0.2).abs() < 1e-3);
+
+        master.set_headroom(false);
+        for _ in 0..SAMPLE_RATE / 10 {
+            master.process((0.4, 0.4));
+        }
+        assert!((master.process((0.4, 0.4)).0 - 0.4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn headroom_falls_back_slowly() {
+        let mut master = Master::new();
+        master.set_voices(4);
+
+        // Voices finishing their release don't raise the gain of the held ones right away
+        let updates_per_second = SAMPLE_RATE as usize / CONTROL_PERIOD;
+        for _ in 0..updates_per_second / 10 {
+            master.set_voices(1);
+        }
+        assert!(master.polyphony.value() > 3.5);
+
+        // A new note within the recent maximum doesn't change the gain
+        master.set_voices(2);
+        assert!(master.polyphony.value() > 3.5);
+
+        for _ in 0..updates_per_second * 10 {
+            master.set_voices(1);
+        }
+        assert!((master.polyphony.value() - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn mute_ramps_to_silence() {
+        let mut master = Master::new();
+        master.set_muted(true);
+        assert!(!master.is_silent());
+
+        let (first, _) = master.process((0.4, 0.4));
+        assert!(first > 0.3);
+
+        for _ in 0..SAMPLE_RATE / 5 {
+            master.process((0.4, 0.4));
+        }
+        assert!(master.is_silent());
+        assert!(master.process((0.4, 0.4)).0 < 1e-5);
+
+        master.set_muted(false);
+        assert!(!master.is_silent());
+        for _ in 0..SAMPLE_RATE / 10 {
+            master.process((0.4, 0.4));
+        }
+        assert!((master.process((0.4, 0.4)).0 - 0.4).abs() < 1e-3);
+    }
+}
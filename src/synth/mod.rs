@@ -1,156 +1,992 @@
+pub mod bend;
+pub mod cc_map;
+pub mod dither;
+pub mod envelope;
+pub mod filter;
+pub mod glide;
+pub mod lfo;
+pub mod master;
+pub mod mod_matrix;
+pub mod osc;
+pub mod patch;
+pub mod play_mode;
+pub mod preset;
+pub mod rng;
+pub mod smooth;
+pub mod unison;
+pub mod velocity;
+pub mod voice;
+pub mod voice_alloc;
 pub mod wavetable;
+mod wavetable_gen;
 
 use defmt::{debug, warn};
-use paw::audio::{osc::simple_form::SimpleFormSource, source::AudioSourceIter};
+use micromath::F32Ext;
 
-use crate::{midi::note::Note, AUDIO_BUFFER, SAMPLE_RATE};
+use crate::midi::{
+    cc,
+    note::Note,
+    param::{RPN_BEND_RANGE, RPN_COARSE_TUNING, RPN_FINE_TUNING, VALUE_CENTER},
+};
 
-#[derive(Clone, Copy)]
-pub enum OscKind {
-    Wave,
-    Noise,
-}
+pub use self::voice::Voice;
+use self::{
+    bend::PitchBend,
+    cc_map::{CcMap, SynthParam},
+    dither::Dither,
+    lfo::Lfo,
+    master::Master,
+    patch::Patch,
+    play_mode::{NotePriority, NoteStack, PlayMode},
+    preset::PresetChange,
+    smooth::Smoother,
+    unison::unison_limit,
+    velocity::VelocityCurve,
+    voice::{GlobalMod, AFTERTOUCH_SMOOTHING_MS, CONTROL_PERIOD},
+    voice_alloc::{Allocation, StealPolicy, VoiceAllocator},
+};
+
+pub const VOICES_COUNT: usize = 16;
+/// Stereo positions given to consecutive notes when spread is enabled
+const SPREAD_POSITIONS: [f32; 8] = [-1.0, 1.0, -0.5, 0.5, -0.75, 0.75, -0.25, 0.25];
 
-#[derive(Clone, Copy)]
-pub enum OscName {
-    Osc1,
-    Osc2,
-    Osc3,
+pub struct Synth {
+    voices: [Voice; VOICES_COUNT],
+    allocator: VoiceAllocator<VOICES_COUNT>,
+    velocity_curve: VelocityCurve,
+    patch: Patch,
+    /// Patch replaced by the last preset change, used by voices ringing out
+    previous_patch: Patch,
+    ringing: [bool; VOICES_COUNT],
+    preset_change: PresetChange,
+    /// Bank selected by CC0/CC32
+    bank: u16,
+    program: u8,
+    preset_name: &'static str,
+    global_lfo: Lfo,
+    global_mod: GlobalMod,
+    pitch_bend: PitchBend,
+    /// Channel aftertouch in 0..1 range
+    aftertouch: Smoother,
+    master: Master,
+    dither: Dither,
+    play_mode: PlayMode,
+    note_priority: NotePriority,
+    /// Held notes for mono modes
+    note_stack: NoteStack,
+    sustain: bool,
+    sostenuto: bool,
+    cc_map: CcMap,
+    /// Tuning set by RPN in semitones
+    coarse_tuning: f32,
+    fine_tuning: f32,
+    spread_counter: usize,
+    control_counter: usize,
 }
 
-impl OscName {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            OscName::Osc1 => "OSC1",
-            OscName::Osc2 => "OSC2",
-            OscName::Osc3 => "OSC3",
+impl Synth {
+    pub fn new() -> Self {
+        Self {
+            voices: core::array::from_fn(|index| Voice::new(index as u32 + 1)),
+            allocator: VoiceAllocator::default(),
+            velocity_curve: VelocityCurve::default(),
+            patch: Patch::default(),
+            previous_patch: Patch::default(),
+            ringing: [false; VOICES_COUNT],
+            preset_change: PresetChange::default(),
+            bank: 0,
+            program: 0,
+            preset_name: preset::FACTORY_BANK[0].name,
+            global_lfo: Lfo::new(0),
+            global_mod: GlobalMod::default(),
+            pitch_bend: PitchBend::new(CONTROL_PERIOD),
+            aftertouch: Smoother::new(AFTERTOUCH_SMOOTHING_MS, CONTROL_PERIOD),
+            master: Master::new(),
+            dither: Dither::default(),
+            play_mode: PlayMode::default(),
+            note_priority: NotePriority::default(),
+            note_stack: NoteStack::default(),
+            sustain: false,
+            sostenuto: false,
+            cc_map: CcMap::default(),
+            coarse_tuning: 0.0,
+            fine_tuning: 0.0,
+            spread_counter: 0,
+            control_counter: 0,
         }
     }
-}
 
-impl core::fmt::Display for OscName {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        self.as_str().fmt(f)
-    }
-}
+    /// Play note with MIDI velocity, velocity of 0 is a note off
+    pub fn note_on(&mut self, note: Note, velocity: u8) {
+        if velocity == 0 {
+            return self.note_off(note);
+        }
 
-pub struct Osc {
-    pub kind: OscKind,
-    pub name: OscName,
-}
+        // Global LFO restarts only when nothing else is held
+        if !self.voices.iter().any(|voice| voice.is_held()) {
+            self.global_lfo.trigger(&self.patch.global_lfo);
+        }
 
-pub struct Voice {
-    sound: SimpleFormSource,
-    note: Option<Note>,
-}
+        if self.play_mode.is_mono() {
+            self.note_stack.push(note, velocity);
+            if let Some((note, velocity)) = self.note_stack.get(self.note_priority) {
+                self.play_mono(note, velocity);
+            }
+            return;
+        }
 
-impl Voice {
-    pub fn note_on(&mut self, note: Note) {
-        self.sound.set_freq(note.freq());
-        self.note = Some(note);
-    }
+        let allocation = self.allocator.allocate(note, &self.voices);
 
-    pub fn note_off(&mut self) {
-        self.note = None;
+        match allocation {
+            Allocation::Free(index) | Allocation::Retrigger(index) => debug!(
+                "Note on {} [voice={}, velocity={}]",
+                format!("{:?}", note).as_str(),
+                index,
+                velocity
+            ),
+            Allocation::Released(index) | Allocation::Stolen(index) => debug!(
+                "Note on {} [voice={}, velocity={}] stealing {}",
+                format!("{:?}", note).as_str(),
+                index,
+                velocity,
+                self.voices[index].current_note()
+            ),
+        }
+
+        // Voice being started counts too if it was idle
+        let active = self.voices.iter().filter(|voice| voice.is_active()).count()
+            + matches!(allocation, Allocation::Free(_)) as usize;
+        // Voices already playing give up copies so the total of oscillators stays within the budget
+        let limit = unison_limit(active, self.patch.active_oscs());
+        self.voices
+            .iter_mut()
+            .for_each(|voice| voice.limit_unison(limit));
+
+        let gain = self.velocity_curve.gain(velocity);
+        self.ringing[allocation.index()] = false;
+        let voice = &mut self.voices[allocation.index()];
+        voice.note_on(note, gain, &self.patch, limit, false);
+        voice.set_spread(SPREAD_POSITIONS[self.spread_counter]);
+        self.spread_counter = (self.spread_counter + 1) % SPREAD_POSITIONS.len();
     }
 
-    pub fn current_note(&self) -> Option<Note> {
-        self.note
+    pub fn note_off(&mut self, note: Note) {
+        if self.play_mode.is_mono() {
+            self.note_stack.remove(note);
+            match self.note_stack.get(self.note_priority) {
+                Some((note, velocity)) => self.play_mono(note, velocity),
+                None if self.voices[0].is_key_down() => {
+                    debug!("Note off {} [mono]", format!("{:?}", note).as_str());
+                    self.voices[0].release_key(self.sustain);
+                }
+                None => {}
+            }
+            return;
+        }
+
+        if let Some(note_voice) = self
+            .voices
+            .iter_mut()
+            .position(|voice| voice.is_key_down() && voice.current_note() == Some(note))
+        {
+            debug!(
+                "Note off {} [voice={}]",
+                format!("{:?}", note).as_str(),
+                note_voice
+            );
+            self.voices[note_voice].release_key(self.sustain);
+        } else {
+            warn!("No voice found with note [{}] to off", note);
+        }
     }
 
-    pub fn next_sample(&mut self) -> Option<f32> {
-        if let Some(_) = self.note {
-            Some(self.sound.next_sample() * 0.2)
+    /// Make the single voice of mono modes play `note` unless it already does
+    fn play_mono(&mut self, note: Note, velocity: u8) {
+        self.ringing[0] = false;
+        let voice = &mut self.voices[0];
+        if voice.is_held() && voice.current_note() == Some(note) {
+            return;
+        }
+
+        debug!(
+            "Note on {} [{}, velocity={}]",
+            format!("{:?}", note).as_str(),
+            self.play_mode.as_str(),
+            velocity
+        );
+
+        if self.play_mode == PlayMode::Legato && voice.is_held() {
+            voice.legato(note, &self.patch);
         } else {
-            None
+            let gain = self.velocity_curve.gain(velocity);
+            voice.note_on(
+                note,
+                gain,
+                &self.patch,
+                unison_limit(1, self.patch.active_oscs()),
+                true,
+            );
+            voice.set_spread(0.0);
         }
     }
-}
 
-impl Default for Voice {
-    fn default() -> Self {
-        let sound = SimpleFormSource::infinite_mono(
-            SAMPLE_RATE,
-            paw::audio::osc::simple_form::WaveForm::Sine,
+    fn update_control(&mut self) {
+        self.global_mod.lfo = self.global_lfo.advance(
+            &self.patch.global_lfo,
+            self.global_mod.tempo,
             0.0,
+            CONTROL_PERIOD,
         );
+        self.global_mod.bend = self.pitch_bend.advance(&self.patch.bend_range);
+        self.global_mod.aftertouch = self.aftertouch.advance();
 
-        Self { sound, note: None }
+        self.master
+            .set_voices(self.voices.iter().filter(|voice| voice.is_active()).count());
     }
-}
 
-pub struct Synth {
-    voices: [Voice; 16],
-}
+    /// Render block of stereo frames, overwriting the contents of `frames`
+    pub fn render(&mut self, frames: &mut [(i32, i32)]) {
+        for frame in frames.iter_mut() {
+            if self.control_counter == 0 {
+                self.update_control();
+            }
+            self.control_counter = (self.control_counter + 1) % CONTROL_PERIOD;
 
-impl Synth {
-    pub fn new() -> Self {
-        Self {
-            voices: Default::default(),
-            // buffer: Default::default(),
-            // queue: Default::default(),
+            let (left, right) = self
+                .voices
+                .iter_mut()
+                .zip(self.ringing.iter())
+                .filter_map(|(voice, &ringing)| {
+                    let patch = if ringing {
+                        &self.previous_patch
+                    } else {
+                        &self.patch
+                    };
+                    voice.next_sample(patch, &self.global_mod)
+                })
+                .fold((0.0, 0.0), |(left, right), (l, r)| (left + l, right + r));
+
+            *frame = self.dither.quantize(self.master.process((left, right)));
         }
     }
 
-    pub fn note_on(&mut self, note: Note) {
-        if let Some(free_voice) = self
-            .voices
-            .iter_mut()
-            .position(|voice| voice.note.is_none())
-        {
-            debug!(
-                "Note on {} [voice={}]",
-                format!("{:?}", note).as_str(),
-                free_voice
-            );
-            self.voices[free_voice].note_on(note);
-        } else {
-            debug!("No free voice to play [{}]", note);
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.allocator.policy()
+    }
+
+    pub fn set_steal_policy(&mut self, policy: StealPolicy) {
+        self.allocator.set_policy(policy);
+    }
+
+    pub fn set_retrigger_same_note(&mut self, retrigger: bool) {
+        self.allocator.set_retrigger_same_note(retrigger);
+    }
+
+    /// Handle MIDI Control Change with 14-bit value, controllers bound in `CcMap` take precedence over built-in ones
+    pub fn control_change(&mut self, control: u8, value: u16) {
+        let position = value.min(0x3FFF) as f32 / 0x3FFF as f32;
+        if let Some(param) = self.cc_map.handle(control) {
+            return self.set_param(param, position);
+        }
+
+        let msb = (value >> 7) as u8;
+        match control {
+            cc::BANK_SELECT => self.bank = value,
+            cc::MOD_WHEEL => self.global_mod.mod_wheel = position,
+            cc::SUSTAIN => self.set_sustain(cc::is_on(msb)),
+            cc::SOSTENUTO => self.set_sostenuto(cc::is_on(msb)),
+            _ => debug!("Unmapped CC {} = {}", control, value),
         }
     }
 
-    pub fn note_off(&mut self, note: Note) {
-        if let Some(note_voice) = self
+    /// Handle Registered Parameter Number change with 14-bit data entry value
+    pub fn registered_param(&mut self, param: u16, value: u16) {
+        let (msb, lsb) = ((value >> 7) as f32, (value & 0x7F) as f32);
+        match param {
+            // Semitones in MSB and cents in LSB, applied to both directions
+            RPN_BEND_RANGE => {
+                let range = msb + lsb / 100.0;
+                self.patch.bend_range.up = range;
+                self.patch.bend_range.down = range;
+            }
+            // Up to a semitone away from the center in both directions
+            RPN_FINE_TUNING => {
+                self.fine_tuning = (value as f32 - VALUE_CENTER as f32) / VALUE_CENTER as f32;
+            }
+            // Semitones from the center of MSB
+            RPN_COARSE_TUNING => self.coarse_tuning = msb - 64.0,
+            _ => debug!("Unsupported RPN {} = {}", param, value),
+        }
+        self.global_mod.tuning = self.coarse_tuning + self.fine_tuning;
+    }
+
+    /// Set parameter from controller position in 0..1 range
+    pub fn set_param(&mut self, param: SynthParam, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        // Times and cutoff are scaled so that the controller has finer resolution at low values
+        match param {
+            SynthParam::Cutoff => self.patch.filter.cutoff = 20.0 * 1_000f32.powf(value),
+            SynthParam::Resonance => self.patch.filter.resonance = value,
+            SynthParam::FilterEnvAmount => self.patch.filter.env_amount = value * 2.0 - 1.0,
+            SynthParam::AmpAttack => self.patch.amp_env.set_attack(value * value * 5_000.0),
+            SynthParam::AmpDecay => self.patch.amp_env.set_decay(value * value * 5_000.0),
+            SynthParam::AmpSustain => self.patch.amp_env.set_sustain(value),
+            SynthParam::AmpRelease => self.patch.amp_env.set_release(value * value * 10_000.0),
+            SynthParam::GlideTime => self.patch.glide.time = value * value * 2_000.0,
+            SynthParam::Pan => self.patch.pan = value * 2.0 - 1.0,
+            SynthParam::Volume => self.master.set_volume(value),
+        }
+    }
+
+    /// Load preset of the selected bank, unknown programs are ignored
+    pub fn program_change(&mut self, program: u8) {
+        let Some(preset) = preset::find(self.bank, program) else {
+            return warn!("No preset {} in bank {}", program, self.bank);
+        };
+
+        debug!(
+            "Program change {}:{} {} [{}]",
+            self.bank, program, preset.name, self.preset_change
+        );
+
+        self.program = program;
+        self.preset_name = preset.name;
+        self.load_patch((preset.patch)());
+    }
+
+    /// Replace the patch, sounding voices are handled according to `PresetChange`
+    pub fn load_patch(&mut self, patch: Patch) {
+        let previous = core::mem::replace(&mut self.patch, patch);
+        match self.preset_change {
+            PresetChange::Cut => {
+                self.voices.iter_mut().for_each(Voice::kill);
+                self.note_stack.clear();
+                self.ringing = [false; VOICES_COUNT];
+            }
+            PresetChange::RingOut => {
+                // Only the patch just replaced is kept, voices still ringing out from an older one are cut
+                self.previous_patch = previous;
+                for (ringing, voice) in self.ringing.iter_mut().zip(self.voices.iter_mut()) {
+                    if *ringing {
+                        voice.kill();
+                    }
+                    *ringing = voice.is_active();
+                }
+            }
+        }
+    }
+
+    pub fn preset_change(&self) -> PresetChange {
+        self.preset_change
+    }
+
+    pub fn set_preset_change(&mut self, change: PresetChange) {
+        self.preset_change = change;
+    }
+
+    pub fn bank(&self) -> u16 {
+        self.bank
+    }
+
+    pub fn program(&self) -> u8 {
+        self.program
+    }
+
+    /// Name of the last loaded preset
+    pub fn preset_name(&self) -> &'static str {
+        self.preset_name
+    }
+
+    pub fn cc_map(&self) -> &CcMap {
+        &self.cc_map
+    }
+
+    pub fn cc_map_mut(&mut self) -> &mut CcMap {
+        &mut self.cc_map
+    }
+
+    pub fn sustain(&self) -> bool {
+        self.sustain
+    }
+
+    /// Sustain pedal (CC64), while down released notes keep sounding until the pedal is lifted
+    pub fn set_sustain(&mut self, down: bool) {
+        if self.sustain && !down {
+            self.voices.iter_mut().for_each(Voice::release_sustain);
+        }
+        self.sustain = down;
+    }
+
+    pub fn sostenuto(&self) -> bool {
+        self.sostenuto
+    }
+
+    /// Sostenuto pedal (CC66), only notes with keys down at the moment the pedal is pressed are held
+    pub fn set_sostenuto(&mut self, down: bool) {
+        match (self.sostenuto, down) {
+            (false, true) => self.voices.iter_mut().for_each(Voice::latch_sostenuto),
+            (true, false) => self.voices.iter_mut().for_each(Voice::release_sostenuto),
+            _ => {}
+        }
+        self.sostenuto = down;
+    }
+
+    pub fn play_mode(&self) -> PlayMode {
+        self.play_mode
+    }
+
+    /// Switch between poly and mono modes, held notes are released
+    pub fn set_play_mode(&mut self, mode: PlayMode) {
+        if mode == self.play_mode {
+            return;
+        }
+
+        self.play_mode = mode;
+        self.note_stack.clear();
+        self.voices
+            .iter_mut()
+            .filter(|voice| voice.is_held())
+            .for_each(Voice::note_off);
+    }
+
+    pub fn note_priority(&self) -> NotePriority {
+        self.note_priority
+    }
+
+    pub fn set_note_priority(&mut self, priority: NotePriority) {
+        self.note_priority = priority;
+    }
+
+    pub fn tempo(&self) -> f32 {
+        self.global_mod.tempo
+    }
+
+    /// Set tempo in BPM for tempo-synced LFOs
+    pub fn set_tempo(&mut self, tempo: f32) {
+        self.global_mod.tempo = tempo.max(1.0);
+    }
+
+    /// Set mod wheel position from MIDI CC value
+    pub fn set_mod_wheel(&mut self, value: u8) {
+        self.global_mod.mod_wheel = value.min(127) as f32 / 127.0;
+    }
+
+    /// Set channel aftertouch from MIDI pressure value, change is smoothed
+    pub fn set_aftertouch(&mut self, value: u8) {
+        self.aftertouch.set_target(value.min(127) as f32 / 127.0);
+    }
+
+    /// Set polyphonic aftertouch of voices holding `note` from MIDI pressure value, change is smoothed
+    pub fn set_poly_aftertouch(&mut self, note: Note, value: u8) {
+        let pressure = value.min(127) as f32 / 127.0;
+        for voice in self
             .voices
             .iter_mut()
-            .position(|voice| voice.note == Some(note))
+            .filter(|voice| voice.is_held() && voice.current_note() == Some(note))
         {
-            debug!(
-                "Note off {} [voice={}]",
-                format!("{:?}", note).as_str(),
-                note_voice
-            );
-            self.voices[note_voice].note_off();
-        } else {
-            warn!("No voice found with note [{}] to off", note);
+            voice.set_pressure(pressure);
         }
     }
 
-    pub fn tick(&mut self) {
-        cortex_m::interrupt::free(|cs| {
-            let mut buffer = AUDIO_BUFFER.borrow(cs).borrow_mut();
-            if !buffer.is_full() {
-                let voices_sample: f32 = self
-                    .voices
-                    .iter_mut()
-                    .filter_map(|voice| voice.next_sample())
-                    .sum();
+    /// Set pitch wheel from 14-bit MIDI value, change is smoothed
+    pub fn set_pitch_bend(&mut self, value: u16) {
+        self.pitch_bend.set(value);
+    }
 
-                let sample = (voices_sample * i32::MAX as f32) as i32;
-                buffer.push_back((sample, sample)).ok();
-            } else {
-                // debug!("Buffer is full!");
-            }
-        });
-        // if !self.queue.is_full() {
-        //     let sample = (self.sound.next_sample() * 0.4 * i32::MAX as f32) as i32;
-        //     self.queue.push_back((sample, sample)).ok();
-        // } else {
-        //     // info!("Buffer is full!");
-        // }
+    pub fn velocity_curve(&self) -> VelocityCurve {
+        self.velocity_curve
+    }
+
+    pub fn set_velocity_curve(&mut self, curve: VelocityCurve) {
+        self.velocity_curve = curve;
+    }
+
+    pub fn master(&self) -> &Master {
+        &self.master
+    }
+
+    pub fn master_mut(&mut self) -> &mut Master {
+        &mut self.master
+    }
+
+    pub fn dither(&self) -> &Dither {
+        &self.dither
+    }
+
+    pub fn dither_mut(&mut self) -> &mut Dither {
+        &mut self.dither
+    }
+
+    pub fn patch(&self) -> &Patch {
+        &self.patch
+    }
+
+    pub fn patch_mut(&mut self) -> &mut Patch {
+        &mut self.patch
     }
 
+    /// Voices that are playing a note, including those in release stage
     pub fn active_voices(&self) -> impl Iterator<Item = &Voice> {
-        self.voices.iter().filter(|voice| voice.note.is_some())
+        self.voices.iter().filter(|voice| voice.is_active())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        glide::GlideParams,
+        master::LIMITER_THRESHOLD,
+        mod_matrix::{ModDest, ModSlot, ModSource},
+        osc::WaveForm,
+        patch::OSC_COUNT,
+        unison::{UnisonParams, OSC_BUDGET},
+        *,
+    };
+    use crate::SAMPLE_RATE;
+
+    const AUDIO_BLOCK: usize = 256;
+
+    #[test]
+    fn silent_without_notes() {
+        let mut synth = Synth::new();
+        let mut frames = [(1, 1); 64];
+        synth.render(&mut frames);
+
+        assert!(frames.iter().all(|&frame| frame == (0, 0)));
+    }
+
+    #[test]
+    fn renders_note_in_blocks() {
+        let mut synth = Synth::new();
+        synth.note_on(Note::A4, 127);
+
+        // Odd block size to check control rate is kept across blocks
+        let mut frames = [(0, 0); 100];
+        let mut peak = 0;
+        for _ in 0..10 {
+            synth.render(&mut frames);
+            peak = frames
+                .iter()
+                .map(|&(left, _)| left.unsigned_abs())
+                .max()
+                .unwrap()
+                .max(peak);
+        }
+
+        assert!(peak > i32::MAX as u32 / 20);
+        assert_eq!(synth.active_voices().count(), 1);
+    }
+
+    /// Sums of squared left and right samples
+    fn energy(synth: &mut Synth) -> (f64, f64) {
+        let mut frames = [(0, 0); AUDIO_BLOCK];
+        let mut energy = (0.0, 0.0);
+        for _ in 0..10 {
+            synth.render(&mut frames);
+            for &(left, right) in frames.iter() {
+                energy.0 += (left as f64).powi(2);
+                energy.1 += (right as f64).powi(2);
+            }
+        }
+        energy
+    }
+
+    #[test]
+    fn pan_and_balance() {
+        let mut synth = Synth::new();
+        synth.note_on(Note::A4, 127);
+        let (left, right) = energy(&mut synth);
+        assert!((left / right - 1.0).abs() < 1e-3);
+
+        let mut synth = Synth::new();
+        synth.patch_mut().pan = 0.5;
+        synth.note_on(Note::A4, 127);
+        let (left, right) = energy(&mut synth);
+        assert!(right > 4.0 * left);
+
+        let mut synth = Synth::new();
+        synth.master_mut().set_balance(-1.0);
+        synth.note_on(Note::A4, 127);
+        // Balance change is smoothed from the centre
+        let (left, right) = energy(&mut synth);
+        assert!(left > 10.0 * right);
+    }
+
+    #[test]
+    fn spread_fans_voices() {
+        let mut synth = Synth::new();
+        synth.patch_mut().spread = 1.0;
+
+        // First two notes go to opposite sides
+        synth.note_on(Note::A4, 127);
+        let (left, right) = energy(&mut synth);
+        assert!(left > 100.0 * right);
+
+        synth.note_off(Note::A4);
+        synth.note_on(Note::A5, 127);
+        for _ in 0..SAMPLE_RATE as usize / AUDIO_BLOCK {
+            synth.render(&mut [(0, 0); AUDIO_BLOCK]);
+        }
+        let (left, right) = energy(&mut synth);
+        assert!(right > 100.0 * left);
+    }
+
+    #[test]
+    fn unison_widens_stereo() {
+        let mut synth = Synth::new();
+        synth.patch_mut().oscs[0].wave = WaveForm::Saw;
+        synth.patch_mut().unison = UnisonParams {
+            voices: 8,
+            stereo: 1.0,
+            ..Default::default()
+        };
+        synth.note_on(Note::A3, 127);
+
+        let mut frames = [(0, 0); AUDIO_BLOCK];
+        let mut differs = false;
+        for _ in 0..10 {
+            synth.render(&mut frames);
+            differs |= frames.iter().any(|&(left, right)| left != right);
+        }
+        assert!(differs);
+
+        // Both sides carry the sound
+        let (left, right) = energy(&mut synth);
+        assert!(left / right > 0.5 && left / right < 2.0);
+    }
+
+    #[test]
+    fn unison_stays_within_budget() {
+        let mut synth = Synth::new();
+        synth.patch_mut().unison.voices = 8;
+        synth.patch_mut().oscs[1].level = 0.5;
+        // Level raised only by modulation counts as well
+        synth.patch_mut().mod_matrix.add(ModSlot::new(
+            ModSource::ModWheel,
+            ModDest::Osc3Level,
+            1.0,
+        ));
+        assert_eq!(synth.patch().active_oscs(), OSC_COUNT);
+
+        for note in 0..VOICES_COUNT as u8 {
+            synth.note_on(Note::C4.transpose(note as i8), 127);
+            let oscs: usize = synth
+                .active_voices()
+                .map(|voice| voice.unison() * OSC_COUNT)
+                .sum();
+            assert!(oscs <= OSC_BUDGET, "{} oscillators", oscs);
+        }
+
+        assert_eq!(synth.active_voices().count(), VOICES_COUNT);
+        assert!(synth.active_voices().all(|voice| voice.unison() >= 1));
+    }
+
+    /// Notes of held voices
+    fn held_notes(synth: &Synth) -> heapless::Vec<Note, VOICES_COUNT> {
+        synth
+            .voices
+            .iter()
+            .filter(|voice| voice.is_held())
+            .filter_map(|voice| voice.current_note())
+            .collect()
+    }
+
+    #[test]
+    fn mono_returns_to_held_note() {
+        let mut synth = Synth::new();
+        synth.set_play_mode(PlayMode::Mono);
+
+        synth.note_on(Note::C4, 100);
+        synth.note_on(Note::E4, 100);
+        synth.note_on(Note::G4, 100);
+        assert_eq!(held_notes(&synth), [Note::G4]);
+
+        synth.note_off(Note::G4);
+        assert_eq!(held_notes(&synth), [Note::E4]);
+        synth.note_off(Note::C4);
+        assert_eq!(held_notes(&synth), [Note::E4]);
+        synth.note_off(Note::E4);
+        assert!(held_notes(&synth).is_empty());
+
+        synth.set_note_priority(NotePriority::Low);
+        synth.note_on(Note::E4, 100);
+        synth.note_on(Note::C4, 100);
+        synth.note_on(Note::G4, 100);
+        assert_eq!(held_notes(&synth), [Note::C4]);
+        synth.note_off(Note::C4);
+        assert_eq!(held_notes(&synth), [Note::E4]);
+
+        // Back to poly releases everything
+        synth.set_play_mode(PlayMode::Poly);
+        assert!(held_notes(&synth).is_empty());
+    }
+
+    #[test]
+    fn legato_glides_without_retrigger() {
+        let mut synth = Synth::new();
+        synth.set_play_mode(PlayMode::Legato);
+        synth.patch_mut().glide = GlideParams {
+            time: 50.0,
+            ..Default::default()
+        };
+
+        synth.note_on(Note::A3, 127);
+        let (first, _) = energy(&mut synth);
+
+        // Level stays the same as envelope is not restarted, pitch moves an octave up
+        synth.note_on(Note::A4, 127);
+        let mut frames = [(0, 0); AUDIO_BLOCK];
+        let mut crossings = [0; 20];
+        for crossings in crossings.iter_mut() {
+            synth.render(&mut frames);
+            *crossings = frames
+                .windows(2)
+                .filter(|pair| pair[0].0 < 0 && pair[1].0 >= 0)
+                .count();
+        }
+        assert_eq!(held_notes(&synth), [Note::A4]);
+        assert!(crossings[0] < crossings[19]);
+
+        let (second, _) = energy(&mut synth);
+        assert!((second / first - 1.0).abs() < 0.1);
+    }
+
+    /// Rising zero crossings of the left channel over `blocks` blocks
+    fn crossings(synth: &mut Synth, blocks: usize) -> usize {
+        let mut frames = [(0, 0); AUDIO_BLOCK];
+        let mut last = 0;
+        let mut count = 0;
+        for _ in 0..blocks {
+            synth.render(&mut frames);
+            for &(left, _) in frames.iter() {
+                count += (last < 0 && left >= 0) as usize;
+                last = left;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn pitch_bend_range() {
+        let mut synth = Synth::new();
+        synth.patch_mut().bend_range.up = 12.0;
+        synth.note_on(Note::A3, 127);
+        let unbent = crossings(&mut synth, 20);
+
+        synth.set_pitch_bend(0x3FFF);
+        // Let smoothing settle
+        crossings(&mut synth, 20);
+        let bent = crossings(&mut synth, 20);
+        assert!(bent.abs_diff(unbent * 2) <= 2, "{} {}", unbent, bent);
+
+        synth.set_pitch_bend(0);
+        crossings(&mut synth, 20);
+        let down = crossings(&mut synth, 20);
+        // Default down range is a whole tone
+        let expected = unbent as f32 / 2f32.powf(2.0 / 12.0);
+        assert!((down as f32 - expected).abs() <= 2.0, "{} {}", unbent, down);
+    }
+
+    #[test]
+    fn aftertouch_is_smoothed_and_per_voice() {
+        let mut synth = Synth::new();
+        synth.set_aftertouch(127);
+        synth.render(&mut [(0, 0); CONTROL_PERIOD]);
+        assert!(synth.global_mod.aftertouch > 0.0 && synth.global_mod.aftertouch < 0.5);
+
+        synth.patch_mut().mod_matrix.add(ModSlot::new(
+            ModSource::PolyAftertouch,
+            ModDest::Volume,
+            -1.0,
+        ));
+        synth.note_on(Note::A4, 127);
+        energy(&mut synth);
+        let (open, _) = energy(&mut synth);
+
+        // Pressure of another note does not affect the voice
+        synth.set_poly_aftertouch(Note::C4, 127);
+        let (other, _) = energy(&mut synth);
+        assert!((other / open - 1.0).abs() < 1e-2, "{} {}", open, other);
+
+        synth.set_poly_aftertouch(Note::A4, 127);
+        energy(&mut synth);
+        let (pressed, _) = energy(&mut synth);
+        assert!(pressed < open / 100.0, "{} {}", open, pressed);
+    }
+
+    #[test]
+    fn sustain_defers_note_off() {
+        let mut synth = Synth::new();
+        synth.note_on(Note::C4, 100);
+        synth.set_sustain(true);
+        synth.note_on(Note::E4, 100);
+        synth.note_off(Note::C4);
+        synth.note_off(Note::E4);
+        assert_eq!(held_notes(&synth), [Note::C4, Note::E4]);
+
+        // Replaying a sustained note reuses its voice
+        synth.note_on(Note::C4, 100);
+        synth.note_off(Note::C4);
+        assert_eq!(held_notes(&synth), [Note::C4, Note::E4]);
+
+        synth.note_on(Note::G4, 100);
+        synth.set_sustain(false);
+        assert_eq!(held_notes(&synth), [Note::G4]);
+    }
+
+    #[test]
+    fn sostenuto_holds_only_notes_down_at_press() {
+        let mut synth = Synth::new();
+        synth.note_on(Note::C4, 100);
+        synth.set_sostenuto(true);
+        synth.note_on(Note::E4, 100);
+        synth.note_off(Note::C4);
+        synth.note_off(Note::E4);
+        assert_eq!(held_notes(&synth), [Note::C4]);
+
+        synth.set_sostenuto(false);
+        assert!(held_notes(&synth).is_empty());
+    }
+
+    #[test]
+    fn pedal_held_voices_are_stolen_first() {
+        let mut synth = Synth::new();
+        synth.set_sustain(true);
+        synth.note_on(Note::C2, 100);
+        synth.note_off(Note::C2);
+
+        // Fill the remaining voices with notes held by keys
+        for index in 1..VOICES_COUNT as u8 {
+            synth.note_on(Note::try_from(40 + index).unwrap(), 100);
+        }
+        synth.note_on(Note::C6, 100);
+
+        let held = held_notes(&synth);
+        assert!(!held.contains(&Note::C2));
+        assert!(held.contains(&Note::C6));
+        assert!(held.contains(&Note::try_from(41).unwrap()));
+    }
+
+    #[test]
+    fn learned_cc_controls_param() {
+        let mut synth = Synth::new();
+        synth.cc_map_mut().start_learn(SynthParam::Resonance);
+        // Resting a foot on the pedal does not capture it
+        synth.control_change(cc::SUSTAIN, 0x3FFF);
+        assert!(synth.sustain());
+        assert_eq!(synth.cc_map().learning(), Some(SynthParam::Resonance));
+
+        synth.control_change(20, 0x3FFF);
+        assert_eq!(synth.patch().filter.resonance, 1.0);
+
+        synth.control_change(20, 0);
+        assert_eq!(synth.patch().filter.resonance, 0.0);
+
+        synth.control_change(cc::CUTOFF, 0);
+        assert!((synth.patch().filter.cutoff - 20.0).abs() < 1e-3);
+        synth.control_change(cc::MOD_WHEEL, 0x3FFF);
+        assert_eq!(synth.global_mod.mod_wheel, 1.0);
+    }
+
+    #[test]
+    fn registered_params() {
+        let mut synth = Synth::new();
+        synth.registered_param(RPN_BEND_RANGE, (12 << 7) | 50);
+        assert_eq!(synth.patch().bend_range.up, 12.5);
+        assert_eq!(synth.patch().bend_range.down, 12.5);
+
+        synth.registered_param(RPN_COARSE_TUNING, 62 << 7);
+        synth.registered_param(RPN_FINE_TUNING, VALUE_CENTER + VALUE_CENTER / 2);
+        assert_eq!(synth.global_mod.tuning, -1.5);
+
+        // Tuning an octave down halves the frequency
+        synth.registered_param(RPN_COARSE_TUNING, 76 << 7);
+        synth.registered_param(RPN_FINE_TUNING, VALUE_CENTER);
+        synth.note_on(Note::A3, 127);
+        let tuned = crossings(&mut synth, 20);
+        let mut synth = Synth::new();
+        synth.note_on(Note::A4, 127);
+        assert!(tuned.abs_diff(crossings(&mut synth, 20)) <= 2);
+    }
+
+    #[test]
+    fn program_change_ring_out_and_cut() {
+        let mut synth = Synth::new();
+        synth.note_on(Note::A4, 127);
+        energy(&mut synth);
+
+        synth.program_change(3);
+        assert_eq!(synth.preset_name(), "Square Bass");
+        assert!(synth.ringing[0]);
+        // Released note keeps its old sound until it ends
+        synth.note_off(Note::A4);
+        energy(&mut synth);
+        assert_eq!(synth.active_voices().count(), 1);
+
+        // New note uses the new patch
+        synth.note_on(Note::A4, 127);
+        assert!(!synth.ringing[0]);
+
+        synth.set_preset_change(PresetChange::Cut);
+        synth.control_change(cc::BANK_SELECT, 1 << 7);
+        synth.program_change(0);
+        assert_eq!(synth.preset_name(), "Square Bass");
+
+        synth.control_change(cc::BANK_SELECT, 0);
+        synth.program_change(0);
+        assert_eq!(synth.preset_name(), "Init");
+        assert_eq!(synth.active_voices().count(), 0);
+    }
+
+    #[test]
+    fn second_program_change_cuts_older_ringing_voices() {
+        let mut synth = Synth::new();
+        synth.note_on(Note::A4, 127);
+        synth.program_change(3);
+        synth.note_off(Note::A4);
+
+        synth.note_on(Note::C4, 127);
+        assert_eq!(synth.active_voices().count(), 2);
+        synth.program_change(0);
+
+        // A4 would otherwise jump to the patch of program 3 mid-release
+        let notes: heapless::Vec<Note, VOICES_COUNT> = synth
+            .active_voices()
+            .filter_map(|voice| voice.current_note())
+            .collect();
+        assert_eq!(notes, [Note::C4]);
+        assert_eq!(synth.ringing.iter().filter(|&&ringing| ringing).count(), 1);
+    }
+
+    #[test]
+    fn all_voices_full_scale_do_not_overflow() {
+        let mut synth = Synth::new();
+        synth.master_mut().set_headroom(false);
+        for osc in synth.patch_mut().oscs.iter_mut() {
+            osc.wave = WaveForm::Square;
+            osc.level = 1.0;
+        }
+
+        // Same note with retrigger disabled stacks voices in phase for the worst case
+        synth.set_retrigger_same_note(false);
+        for _ in 0..VOICES_COUNT {
+            synth.note_on(Note::A2, 127);
+        }
+        assert_eq!(synth.active_voices().count(), VOICES_COUNT);
+
+        let ceiling = (LIMITER_THRESHOLD * i32::MAX as f32) as u32 + 1;
+        let mut frames = [(0, 0); AUDIO_BLOCK];
+        let mut peak = 0;
+        for _ in 0..50 {
+            synth.render(&mut frames);
+            for &(left, right) in frames.iter() {
+                assert_eq!(left, right);
+                assert!(left.unsigned_abs() <= ceiling);
+                peak = peak.max(left.unsigned_abs());
+            }
+        }
+
+        // Limiter kicked in rather than everything being quiet
+        assert!(peak > ceiling / 2);
     }
 }
@@ -0,0 +1,465 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use super::{filter::ENV_AMOUNT_OCTAVES, patch::OSC_COUNT};
+
+pub const MOD_SLOTS: usize = 8;
+/// Size of serialized `ModSlot`
+pub const MOD_SLOT_BYTES: usize = 4;
+
+/// Pitch shift at full modulation amount in semitones
+const PITCH_RANGE: f32 = 24.0;
+/// LFO rate shift at full modulation amount in octaves
+const LFO_RATE_RANGE: f32 = 4.0;
+/// Key source is centered at C4 and reaches full value 5 octaves away
+const KEY_CENTER: f32 = 60.0;
+const KEY_RANGE: f32 = 60.0;
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format, IntoPrimitive, TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum ModSource {
+    #[default]
+    Off,
+    AmpEnv,
+    FilterEnv,
+    VoiceLfo,
+    GlobalLfo,
+    Velocity,
+    /// Played note relative to C4, bipolar
+    Key,
+    ModWheel,
+    /// Channel aftertouch, shared by all voices
+    Aftertouch,
+    /// Polyphonic aftertouch of the voice's note
+    PolyAftertouch,
+}
+
+impl ModSource {
+    pub const ALL: [Self; 10] = [
+        Self::Off,
+        Self::AmpEnv,
+        Self::FilterEnv,
+        Self::VoiceLfo,
+        Self::GlobalLfo,
+        Self::Velocity,
+        Self::Key,
+        Self::ModWheel,
+        Self::Aftertouch,
+        Self::PolyAftertouch,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModSource::Off => "Off",
+            ModSource::AmpEnv => "AmpEnv",
+            ModSource::FilterEnv => "FltEnv",
+            ModSource::VoiceLfo => "LFO",
+            ModSource::GlobalLfo => "GLFO",
+            ModSource::Velocity => "Vel",
+            ModSource::Key => "Key",
+            ModSource::ModWheel => "ModWhl",
+            ModSource::Aftertouch => "AT",
+            ModSource::PolyAftertouch => "PolyAT",
+        }
+    }
+}
+
+impl core::fmt::Display for ModSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format, IntoPrimitive, TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum ModDest {
+    /// Pitch of all oscillators
+    #[default]
+    Pitch,
+    Osc1Pitch,
+    Osc2Pitch,
+    Osc3Pitch,
+    Osc1Level,
+    Osc2Level,
+    Osc3Level,
+    Cutoff,
+    Resonance,
+    Pan,
+    LfoRate,
+    /// Vibrato depth of the voice LFO
+    VibratoDepth,
+    Volume,
+}
+
+impl ModDest {
+    pub const ALL: [Self; 13] = [
+        Self::Pitch,
+        Self::Osc1Pitch,
+        Self::Osc2Pitch,
+        Self::Osc3Pitch,
+        Self::Osc1Level,
+        Self::Osc2Level,
+        Self::Osc3Level,
+        Self::Cutoff,
+        Self::Resonance,
+        Self::Pan,
+        Self::LfoRate,
+        Self::VibratoDepth,
+        Self::Volume,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModDest::Pitch => "Pitch",
+            ModDest::Osc1Pitch => "Osc1 Pitch",
+            ModDest::Osc2Pitch => "Osc2 Pitch",
+            ModDest::Osc3Pitch => "Osc3 Pitch",
+            ModDest::Osc1Level => "Osc1 Level",
+            ModDest::Osc2Level => "Osc2 Level",
+            ModDest::Osc3Level => "Osc3 Level",
+            ModDest::Cutoff => "Cutoff",
+            ModDest::Resonance => "Reso",
+            ModDest::Pan => "Pan",
+            ModDest::LfoRate => "LFO Rate",
+            ModDest::VibratoDepth => "Vibrato",
+            ModDest::Volume => "Volume",
+        }
+    }
+
+    /// Destination change at full modulation amount, in destination units
+    pub fn range(&self) -> f32 {
+        match self {
+            ModDest::Pitch | ModDest::Osc1Pitch | ModDest::Osc2Pitch | ModDest::Osc3Pitch => {
+                PITCH_RANGE
+            }
+            ModDest::Cutoff => ENV_AMOUNT_OCTAVES,
+            ModDest::LfoRate => LFO_RATE_RANGE,
+            ModDest::Osc1Level
+            | ModDest::Osc2Level
+            | ModDest::Osc3Level
+            | ModDest::Resonance
+            | ModDest::Pan
+            | ModDest::VibratoDepth
+            | ModDest::Volume => 1.0,
+        }
+    }
+}
+
+impl core::fmt::Display for ModDest {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Single routing of the matrix
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModSlot {
+    pub source: ModSource,
+    pub dest: ModDest,
+    /// Bipolar amount in -1..1 range, scaled by `ModDest::range`
+    pub amount: f32,
+}
+
+impl ModSlot {
+    pub fn new(source: ModSource, dest: ModDest, amount: f32) -> Self {
+        Self {
+            source,
+            dest,
+            amount: amount.clamp(-1.0, 1.0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.source != ModSource::Off && self.amount != 0.0
+    }
+
+    /// Serialize as source, destination and amount as little-endian i16
+    pub fn to_bytes(&self) -> [u8; MOD_SLOT_BYTES] {
+        let amount = (self.amount.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        let [amount_lo, amount_hi] = amount.to_le_bytes();
+
+        [self.source.into(), self.dest.into(), amount_lo, amount_hi]
+    }
+
+    /// Deserialize slot written by `to_bytes`, `None` for unknown source or destination
+    pub fn from_bytes(bytes: [u8; MOD_SLOT_BYTES]) -> Option<Self> {
+        let source = ModSource::try_from(bytes[0]).ok()?;
+        let dest = ModDest::try_from(bytes[1]).ok()?;
+        let amount = i16::from_le_bytes([bytes[2], bytes[3]]) as f32 / i16::MAX as f32;
+
+        Some(Self::new(source, dest, amount))
+    }
+}
+
+/// Current values of modulation sources for a voice
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ModSources {
+    pub amp_env: f32,
+    pub filter_env: f32,
+    pub voice_lfo: f32,
+    pub global_lfo: f32,
+    pub velocity: f32,
+    /// Fractional MIDI pitch
+    pub pitch: f32,
+    pub mod_wheel: f32,
+    pub aftertouch: f32,
+    pub poly_aftertouch: f32,
+}
+
+impl ModSources {
+    pub fn get(&self, source: ModSource) -> f32 {
+        match source {
+            ModSource::Off => 0.0,
+            ModSource::AmpEnv => self.amp_env,
+            ModSource::FilterEnv => self.filter_env,
+            ModSource::VoiceLfo => self.voice_lfo,
+            ModSource::GlobalLfo => self.global_lfo,
+            ModSource::Velocity => self.velocity,
+            ModSource::Key => ((self.pitch - KEY_CENTER) / KEY_RANGE).clamp(-1.0, 1.0),
+            ModSource::ModWheel => self.mod_wheel,
+            ModSource::Aftertouch => self.aftertouch,
+            ModSource::PolyAftertouch => self.poly_aftertouch,
+        }
+    }
+}
+
+/// Sum of modulations per destination, in destination units
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModValues {
+    /// Per-oscillator pitch shift in semitones
+    pub pitch: [f32; OSC_COUNT],
+    /// Per-oscillator level offset
+    pub level: [f32; OSC_COUNT],
+    /// Cutoff shift in octaves
+    pub cutoff: f32,
+    pub resonance: f32,
+    /// Pan offset in -1..1 range
+    pub pan: f32,
+    /// LFO rate shift in octaves
+    pub lfo_rate: f32,
+    /// Vibrato depth added to the voice LFO, in semitones
+    pub vibrato: f32,
+    /// Gain offset, -1 silences the voice and 1 doubles its level
+    pub volume: f32,
+}
+
+impl ModValues {
+    fn add(&mut self, dest: ModDest, value: f32) {
+        match dest {
+            ModDest::Pitch => self.pitch.iter_mut().for_each(|pitch| *pitch += value),
+            ModDest::Osc1Pitch => self.pitch[0] += value,
+            ModDest::Osc2Pitch => self.pitch[1] += value,
+            ModDest::Osc3Pitch => self.pitch[2] += value,
+            ModDest::Osc1Level => self.level[0] += value,
+            ModDest::Osc2Level => self.level[1] += value,
+            ModDest::Osc3Level => self.level[2] += value,
+            ModDest::Cutoff => self.cutoff += value,
+            ModDest::Resonance => self.resonance += value,
+            ModDest::Pan => self.pan += value,
+            ModDest::LfoRate => self.lfo_rate += value,
+            ModDest::VibratoDepth => self.vibrato += value,
+            ModDest::Volume => self.volume += value,
+        }
+    }
+}
+
+/// Routing table of modulation sources to destinations, evaluated per voice at control rate
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModMatrix {
+    slots: [ModSlot; MOD_SLOTS],
+}
+
+impl ModMatrix {
+    pub fn slots(&self) -> &[ModSlot; MOD_SLOTS] {
+        &self.slots
+    }
+
+    pub fn slot(&self, index: usize) -> &ModSlot {
+        &self.slots[index]
+    }
+
+    pub fn slot_mut(&mut self, index: usize) -> &mut ModSlot {
+        &mut self.slots[index]
+    }
+
+    /// Put routing into the first unused slot, returns its index or `None` if the matrix is full
+    pub fn add(&mut self, slot: ModSlot) -> Option<usize> {
+        let index = self.slots.iter().position(|slot| !slot.is_enabled())?;
+        self.slots[index] = slot;
+        Some(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = Default::default();
+    }
+
+    /// Level of oscillator `index` is a destination of an enabled slot
+    pub fn modulates_level(&self, index: usize) -> bool {
+        self.slots
+            .iter()
+            .filter(|slot| slot.is_enabled())
+            .any(|slot| {
+                matches!(
+                    (slot.dest, index),
+                    (ModDest::Osc1Level, 0) | (ModDest::Osc2Level, 1) | (ModDest::Osc3Level, 2)
+                )
+            })
+    }
+
+    pub fn eval(&self, sources: &ModSources) -> ModValues {
+        let mut values = ModValues::default();
+
+        for slot in self.slots.iter().filter(|slot| slot.is_enabled()) {
+            values.add(
+                slot.dest,
+                sources.get(slot.source) * slot.amount * slot.dest.range(),
+            );
+        }
+
+        values
+    }
+
+    pub fn to_bytes(&self) -> [u8; MOD_SLOTS * MOD_SLOT_BYTES] {
+        let mut bytes = [0; MOD_SLOTS * MOD_SLOT_BYTES];
+        for (chunk, slot) in bytes
+            .chunks_exact_mut(MOD_SLOT_BYTES)
+            .zip(self.slots.iter())
+        {
+            chunk.copy_from_slice(&slot.to_bytes());
+        }
+        bytes
+    }
+
+    /// Deserialize matrix written by `to_bytes`, invalid slots are left empty
+    pub fn from_bytes(bytes: &[u8; MOD_SLOTS * MOD_SLOT_BYTES]) -> Self {
+        let mut matrix = Self::default();
+        for (slot, chunk) in matrix
+            .slots
+            .iter_mut()
+            .zip(bytes.chunks_exact(MOD_SLOT_BYTES))
+        {
+            *slot =
+                ModSlot::from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]).unwrap_or_default();
+        }
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_matrix_has_no_effect() {
+        let sources = ModSources {
+            amp_env: 1.0,
+            mod_wheel: 1.0,
+            ..Default::default()
+        };
+
+        assert_eq!(ModMatrix::default().eval(&sources), ModValues::default());
+    }
+
+    #[test]
+    fn routes_are_summed_and_scaled() {
+        let mut matrix = ModMatrix::default();
+        matrix.add(ModSlot::new(ModSource::ModWheel, ModDest::Pitch, 0.5));
+        matrix.add(ModSlot::new(ModSource::VoiceLfo, ModDest::Osc2Pitch, 0.25));
+        matrix.add(ModSlot::new(ModSource::Velocity, ModDest::Cutoff, -0.5));
+        matrix.add(ModSlot::new(ModSource::FilterEnv, ModDest::Cutoff, 0.25));
+
+        let values = matrix.eval(&ModSources {
+            mod_wheel: 0.5,
+            voice_lfo: -1.0,
+            velocity: 1.0,
+            filter_env: 1.0,
+            ..Default::default()
+        });
+
+        assert_eq!(values.pitch, [6.0, 0.0, 6.0]);
+        assert_eq!(values.cutoff, -2.0);
+        assert_eq!(values.lfo_rate, 0.0);
+    }
+
+    #[test]
+    fn key_source_is_centered() {
+        let sources = |pitch| ModSources {
+            pitch,
+            ..Default::default()
+        };
+
+        assert_eq!(sources(60.0).get(ModSource::Key), 0.0);
+        assert_eq!(sources(120.0).get(ModSource::Key), 1.0);
+        assert_eq!(sources(0.0).get(ModSource::Key), -1.0);
+    }
+
+    #[test]
+    fn add_fails_when_full() {
+        let mut matrix = ModMatrix::default();
+        for index in 0..MOD_SLOTS {
+            assert_eq!(
+                matrix.add(ModSlot::new(ModSource::AmpEnv, ModDest::Pan, 1.0)),
+                Some(index)
+            );
+        }
+
+        assert_eq!(
+            matrix.add(ModSlot::new(ModSource::AmpEnv, ModDest::Pan, 1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn serialization_round_trip() {
+        let mut matrix = ModMatrix::default();
+        matrix.add(ModSlot::new(ModSource::Aftertouch, ModDest::Resonance, 0.3));
+        matrix.add(ModSlot::new(ModSource::Key, ModDest::LfoRate, -1.0));
+        *matrix.slot_mut(5) = ModSlot::new(ModSource::GlobalLfo, ModDest::Osc3Level, 1.0);
+
+        let restored = ModMatrix::from_bytes(&matrix.to_bytes());
+        for (restored, slot) in restored.slots().iter().zip(matrix.slots()) {
+            assert_eq!(restored.source, slot.source);
+            assert_eq!(restored.dest, slot.dest);
+            assert!((restored.amount - slot.amount).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn aftertouch_sources() {
+        let mut matrix = ModMatrix::default();
+        matrix.add(ModSlot::new(
+            ModSource::Aftertouch,
+            ModDest::VibratoDepth,
+            0.5,
+        ));
+        matrix.add(ModSlot::new(
+            ModSource::PolyAftertouch,
+            ModDest::Volume,
+            1.0,
+        ));
+        matrix.add(ModSlot::new(
+            ModSource::PolyAftertouch,
+            ModDest::Cutoff,
+            0.5,
+        ));
+
+        let values = matrix.eval(&ModSources {
+            aftertouch: 1.0,
+            poly_aftertouch: 0.5,
+            ..Default::default()
+        });
+
+        assert_eq!(values.vibrato, 0.5);
+        assert_eq!(values.volume, 0.5);
+        assert_eq!(values.cutoff, ENV_AMOUNT_OCTAVES * 0.25);
+    }
+
+    #[test]
+    fn invalid_bytes_are_ignored() {
+        assert_eq!(ModSlot::from_bytes([0xff, 0, 0, 0]), None);
+        assert_eq!(ModSlot::from_bytes([1, 0xff, 0, 0]), None);
+    }
+}
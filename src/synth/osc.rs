@@ -0,0 +1,256 @@
+use core::f32::consts::TAU;
+
+use micromath::F32Ext;
+
+use super::{
+    rng::Rng,
+    wavetable::{band_limited, Interpolation, Mipmap, Wavetable, WavetableOsc},
+};
+use crate::SAMPLE_RATE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum OscKind {
+    Wave,
+    Noise,
+    /// Play `Osc::wavetable` frames morphed by `Osc::morph`, plays `Osc::wave` when there are no frames
+    Wavetable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum WaveForm {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+}
+
+impl WaveForm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WaveForm::Sine => "Sine",
+            WaveForm::Saw => "Saw",
+            WaveForm::Square => "Square",
+            WaveForm::Triangle => "Tri",
+        }
+    }
+
+    /// Sample of the waveform at `phase` in 0..1 range
+    pub fn sample(&self, phase: f32) -> f32 {
+        match self {
+            WaveForm::Sine => (phase * TAU).sin(),
+            WaveForm::Saw => phase * 2.0 - 1.0,
+            WaveForm::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            WaveForm::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum OscName {
+    Osc1,
+    Osc2,
+    Osc3,
+}
+
+impl OscName {
+    pub const ALL: [OscName; 3] = [OscName::Osc1, OscName::Osc2, OscName::Osc3];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OscName::Osc1 => "OSC1",
+            OscName::Osc2 => "OSC2",
+            OscName::Osc3 => "OSC3",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl core::fmt::Display for OscName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Oscillator parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Osc {
+    pub kind: OscKind,
+    pub name: OscName,
+    pub wave: WaveForm,
+    pub octave: i8,
+    pub semitone: i8,
+    /// Fine detune in cents
+    pub fine: f32,
+    pub level: f32,
+    pub wavetable: &'static [Wavetable],
+    /// Wavetable morph position in 0..1 range
+    pub morph: f32,
+    pub interpolation: Interpolation,
+}
+
+impl Osc {
+    pub fn new(name: OscName) -> Self {
+        Self {
+            kind: OscKind::Wave,
+            name,
+            wave: WaveForm::Sine,
+            octave: 0,
+            semitone: 0,
+            fine: 0.0,
+            level: 0.0,
+            wavetable: &[],
+            morph: 0.0,
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    /// Pitch offset relative to played note in semitones
+    pub fn detune(&self) -> f32 {
+        self.octave as f32 * 12.0 + self.semitone as f32 + self.fine / 100.0
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.level > 0.0
+    }
+}
+
+/// Per-voice oscillator state
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OscState {
+    phase: f32,
+    phase_inc: f32,
+    mip_level: usize,
+    rng: Rng,
+    wavetable: WavetableOsc,
+}
+
+impl OscState {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            phase: 0.0,
+            phase_inc: 0.0,
+            mip_level: 0,
+            rng: Rng::new(seed),
+            wavetable: WavetableOsc::default(),
+        }
+    }
+
+    pub fn set_freq(&mut self, freq: f32) {
+        self.phase_inc = (freq / SAMPLE_RATE as f32).min(0.5);
+        self.mip_level = Mipmap::level_for(freq);
+        self.wavetable.set_freq(freq);
+    }
+
+    pub fn reset_phase(&mut self) {
+        self.phase = 0.0;
+        self.wavetable.set_phase(0.0);
+    }
+
+    pub fn randomize_phase(&mut self) {
+        let phase = self.rng.next_f32();
+        self.phase = phase;
+        self.wavetable.set_phase(phase);
+    }
+
+    pub fn next_sample(&mut self, osc: &Osc) -> f32 {
+        let sample = match osc.kind {
+            OscKind::Wavetable if !osc.wavetable.is_empty() => {
+                self.wavetable.set_morph(osc.morph);
+                self.wavetable.set_interpolation(osc.interpolation);
+                return self.wavetable.next_sample(osc.wavetable);
+            }
+            OscKind::Wave | OscKind::Wavetable => match band_limited().get(osc.wave) {
+                Some(mipmap) => mipmap.read(self.phase, self.mip_level, osc.interpolation),
+                None => osc.wave.sample(self.phase),
+            },
+            OscKind::Noise => self.rng.next_bipolar(),
+        };
+
+        self.phase += self.phase_inc;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveforms_are_bounded() {
+        for wave in [
+            WaveForm::Sine,
+            WaveForm::Saw,
+            WaveForm::Square,
+            WaveForm::Triangle,
+        ] {
+            for i in 0..1000 {
+                let sample = wave.sample(i as f32 / 1000.0);
+                assert!((-1.0..=1.0).contains(&sample), "{:?} out of range", wave);
+            }
+        }
+    }
+
+    #[test]
+    fn osc_frequency() {
+        let osc = Osc {
+            wave: WaveForm::Sine,
+            ..Osc::new(OscName::Osc1)
+        };
+        let mut state = OscState::default();
+        state.set_freq(480.0);
+
+        // Sine rises through zero once per period, 100 samples at 48kHz
+        let mut crossings = 0u32;
+        let mut last = state.next_sample(&osc);
+        for _ in 0..SAMPLE_RATE {
+            let sample = state.next_sample(&osc);
+            if last < 0.0 && sample >= 0.0 {
+                crossings += 1;
+            }
+            last = sample;
+        }
+
+        assert!(crossings.abs_diff(480) <= 1);
+    }
+
+    #[test]
+    fn wavetable_without_frames_plays_wave() {
+        let osc = Osc {
+            kind: OscKind::Wavetable,
+            wave: WaveForm::Square,
+            ..Osc::new(OscName::Osc1)
+        };
+        let mut state = OscState::default();
+        state.set_freq(480.0);
+
+        let peak = (0..100)
+            .map(|_| state.next_sample(&osc).abs())
+            .fold(0.0, f32::max);
+        assert!(peak > 0.9);
+    }
+
+    #[test]
+    fn detune() {
+        let osc = Osc {
+            octave: -1,
+            semitone: 7,
+            fine: 50.0,
+            ..Osc::new(OscName::Osc2)
+        };
+
+        assert!((osc.detune() + 4.5).abs() < 1e-6);
+    }
+}
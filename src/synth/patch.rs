@@ -0,0 +1,75 @@
+use super::{
+    bend::BendRange,
+    envelope::AdsrParams,
+    filter::FilterParams,
+    glide::GlideParams,
+    lfo::LfoParams,
+    mod_matrix::ModMatrix,
+    osc::{Osc, OscName, WaveForm},
+    unison::UnisonParams,
+};
+
+pub const OSC_COUNT: usize = 3;
+
+/// Sound parameters shared by all voices
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch {
+    pub oscs: [Osc; OSC_COUNT],
+    pub amp_env: AdsrParams,
+    pub filter: FilterParams,
+    pub filter_env: AdsrParams,
+    /// LFO running separately for each voice
+    pub voice_lfo: LfoParams,
+    /// LFO shared by all voices
+    pub global_lfo: LfoParams,
+    pub mod_matrix: ModMatrix,
+    /// Pan position in -1..1 range
+    pub pan: f32,
+    /// How far voices are fanned across the stereo field, 0..1 range
+    pub spread: f32,
+    pub unison: UnisonParams,
+    pub glide: GlideParams,
+    pub bend_range: BendRange,
+}
+
+impl Patch {
+    pub fn osc(&self, name: OscName) -> &Osc {
+        &self.oscs[name.index()]
+    }
+
+    pub fn osc_mut(&mut self, name: OscName) -> &mut Osc {
+        &mut self.oscs[name.index()]
+    }
+
+    /// Count of oscillators each unison copy runs, the enabled ones and those the mod matrix may raise
+    pub fn active_oscs(&self) -> usize {
+        (0..OSC_COUNT)
+            .filter(|&index| {
+                self.oscs[index].is_enabled() || self.mod_matrix.modulates_level(index)
+            })
+            .count()
+    }
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        let mut oscs = OscName::ALL.map(Osc::new);
+        oscs[0].wave = WaveForm::Sine;
+        oscs[0].level = 1.0;
+
+        Self {
+            oscs,
+            amp_env: AdsrParams::default(),
+            filter: FilterParams::default(),
+            filter_env: AdsrParams::default(),
+            voice_lfo: LfoParams::default(),
+            global_lfo: LfoParams::default(),
+            mod_matrix: ModMatrix::default(),
+            pan: 0.0,
+            spread: 0.0,
+            unison: UnisonParams::default(),
+            glide: GlideParams::default(),
+            bend_range: BendRange::default(),
+        }
+    }
+}
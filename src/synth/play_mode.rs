@@ -0,0 +1,161 @@
+use crate::midi::note::Note;
+
+/// Count of held notes remembered in mono modes, the oldest is forgotten on overflow
+pub const NOTE_STACK_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum PlayMode {
+    #[default]
+    Poly,
+    /// Single voice, every note retriggers envelopes
+    Mono,
+    /// Single voice, notes played while another is held only change pitch
+    Legato,
+}
+
+impl PlayMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlayMode::Poly => "Poly",
+            PlayMode::Mono => "Mono",
+            PlayMode::Legato => "Legato",
+        }
+    }
+
+    pub fn is_mono(&self) -> bool {
+        !matches!(self, PlayMode::Poly)
+    }
+}
+
+impl core::fmt::Display for PlayMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Which of the held notes sounds in mono modes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum NotePriority {
+    #[default]
+    Last,
+    Low,
+    High,
+}
+
+impl NotePriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotePriority::Last => "Last",
+            NotePriority::Low => "Low",
+            NotePriority::High => "High",
+        }
+    }
+}
+
+impl core::fmt::Display for NotePriority {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Held notes with their velocities in the order they were pressed
+#[derive(Clone, Debug, Default)]
+pub struct NoteStack {
+    notes: heapless::Vec<(Note, u8), NOTE_STACK_SIZE>,
+}
+
+impl NoteStack {
+    pub fn push(&mut self, note: Note, velocity: u8) {
+        self.remove(note);
+        if self.notes.is_full() {
+            self.notes.remove(0);
+        }
+        self.notes.push((note, velocity)).ok();
+    }
+
+    pub fn remove(&mut self, note: Note) {
+        self.notes.retain(|&(held, _)| held != note);
+    }
+
+    pub fn clear(&mut self) {
+        self.notes.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Note that should sound with `priority` and its velocity
+    pub fn get(&self, priority: NotePriority) -> Option<(Note, u8)> {
+        match priority {
+            NotePriority::Last => self.notes.last(),
+            NotePriority::Low => self.notes.iter().min_by_key(|(note, _)| u8::from(*note)),
+            NotePriority::High => self.notes.iter().max_by_key(|(note, _)| u8::from(*note)),
+        }
+        .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_priority_returns_to_previous_note() {
+        let mut stack = NoteStack::default();
+        stack.push(Note::C4, 100);
+        stack.push(Note::E4, 90);
+        stack.push(Note::G4, 80);
+        assert_eq!(stack.get(NotePriority::Last), Some((Note::G4, 80)));
+
+        stack.remove(Note::G4);
+        assert_eq!(stack.get(NotePriority::Last), Some((Note::E4, 90)));
+
+        // Releasing a note that is not sounding changes nothing
+        stack.remove(Note::C4);
+        assert_eq!(stack.get(NotePriority::Last), Some((Note::E4, 90)));
+
+        stack.remove(Note::E4);
+        assert!(stack.is_empty());
+        assert_eq!(stack.get(NotePriority::Last), None);
+    }
+
+    #[test]
+    fn low_and_high_priority() {
+        let mut stack = NoteStack::default();
+        stack.push(Note::E4, 1);
+        stack.push(Note::C4, 2);
+        stack.push(Note::G4, 3);
+
+        assert_eq!(stack.get(NotePriority::Low), Some((Note::C4, 2)));
+        assert_eq!(stack.get(NotePriority::High), Some((Note::G4, 3)));
+
+        stack.remove(Note::C4);
+        assert_eq!(stack.get(NotePriority::Low), Some((Note::E4, 1)));
+    }
+
+    #[test]
+    fn repeated_note_moves_to_top() {
+        let mut stack = NoteStack::default();
+        stack.push(Note::C4, 1);
+        stack.push(Note::D4, 1);
+        stack.push(Note::C4, 2);
+
+        assert_eq!(stack.get(NotePriority::Last), Some((Note::C4, 2)));
+        stack.remove(Note::C4);
+        assert_eq!(stack.get(NotePriority::Last), Some((Note::D4, 1)));
+    }
+
+    #[test]
+    fn overflow_forgets_oldest() {
+        let mut stack = NoteStack::default();
+        for pitch in 0..NOTE_STACK_SIZE as u8 + 1 {
+            stack.push(Note::try_from(40 + pitch).unwrap(), 1);
+        }
+
+        assert_eq!(
+            stack.get(NotePriority::Low),
+            Some((Note::try_from(41).unwrap(), 1))
+        );
+    }
+}
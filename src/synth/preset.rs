@@ -0,0 +1,215 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use super::{
+    envelope::AdsrParams,
+    filter::FilterParams,
+    glide::{GlideMode, GlideParams},
+    mod_matrix::{ModDest, ModSlot, ModSource},
+    osc::{OscKind, WaveForm},
+    patch::Patch,
+    unison::UnisonParams,
+    wavetable::{Interpolation, BASIC_SHAPES},
+};
+
+/// What happens to sounding voices when another preset is loaded
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format, IntoPrimitive, TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum PresetChange {
+    /// Silence all voices immediately
+    Cut,
+    /// Sounding voices finish with the previous patch, new notes use the new one
+    #[default]
+    RingOut,
+}
+
+impl PresetChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresetChange::Cut => "Cut",
+            PresetChange::RingOut => "Ring",
+        }
+    }
+}
+
+impl core::fmt::Display for PresetChange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Stored patch selectable by Bank Select and Program Change
+pub struct Preset {
+    pub name: &'static str,
+    pub patch: fn() -> Patch,
+}
+
+/// Presets of bank 0
+pub const FACTORY_BANK: &[Preset] = &[
+    Preset {
+        name: "Init",
+        patch: Patch::default,
+    },
+    Preset {
+        name: "Saw Lead",
+        patch: saw_lead,
+    },
+    Preset {
+        name: "Super Pad",
+        patch: super_pad,
+    },
+    Preset {
+        name: "Square Bass",
+        patch: square_bass,
+    },
+    Preset {
+        name: "Pluck",
+        patch: pluck,
+    },
+    Preset {
+        name: "Noise Sweep",
+        patch: noise_sweep,
+    },
+    Preset {
+        name: "Shape Morph",
+        patch: shape_morph,
+    },
+];
+
+/// Preset stored at `program` of 14-bit `bank`
+pub fn find(bank: u16, program: u8) -> Option<&'static Preset> {
+    match bank {
+        0 => FACTORY_BANK.get(program as usize),
+        _ => None,
+    }
+}
+
+fn saw_lead() -> Patch {
+    let mut patch = Patch::default();
+    patch.oscs[0].wave = WaveForm::Saw;
+    patch.oscs[1].wave = WaveForm::Saw;
+    patch.oscs[1].fine = 7.0;
+    patch.oscs[1].level = 0.7;
+    patch.filter = FilterParams {
+        cutoff: 2_500.0,
+        resonance: 0.3,
+        key_tracking: 0.5,
+        env_amount: 0.3,
+        ..Default::default()
+    };
+    patch.glide = GlideParams {
+        mode: GlideMode::ConstantTime,
+        time: 60.0,
+    };
+    patch
+        .mod_matrix
+        .add(ModSlot::new(ModSource::ModWheel, ModDest::Cutoff, 0.5));
+    patch
+}
+
+fn super_pad() -> Patch {
+    let mut patch = Patch::default();
+    patch.oscs[0].wave = WaveForm::Saw;
+    patch.amp_env = AdsrParams::new(800.0, 500.0, 0.9, 1_500.0);
+    patch.filter = FilterParams {
+        cutoff: 1_800.0,
+        resonance: 0.1,
+        ..Default::default()
+    };
+    patch.unison = UnisonParams {
+        voices: 5,
+        detune: 30.0,
+        stereo: 0.8,
+        random_phase: true,
+    };
+    patch.spread = 0.5;
+    patch
+}
+
+fn square_bass() -> Patch {
+    let mut patch = Patch::default();
+    patch.oscs[0].wave = WaveForm::Square;
+    patch.oscs[0].octave = -1;
+    patch.oscs[1].wave = WaveForm::Sine;
+    patch.oscs[1].octave = -2;
+    patch.oscs[1].level = 0.6;
+    patch.amp_env = AdsrParams::new(2.0, 300.0, 0.7, 80.0);
+    patch.filter = FilterParams {
+        cutoff: 300.0,
+        resonance: 0.4,
+        env_amount: 0.6,
+        env_velocity: 0.5,
+        ..Default::default()
+    };
+    patch.filter_env = AdsrParams::new(1.0, 250.0, 0.0, 80.0);
+    patch
+}
+
+fn pluck() -> Patch {
+    let mut patch = Patch::default();
+    patch.oscs[0].wave = WaveForm::Triangle;
+    patch.oscs[1].wave = WaveForm::Saw;
+    patch.oscs[1].octave = 1;
+    patch.oscs[1].level = 0.3;
+    patch.amp_env = AdsrParams::new(1.0, 400.0, 0.0, 300.0);
+    patch.filter = FilterParams {
+        cutoff: 800.0,
+        key_tracking: 1.0,
+        env_amount: 0.5,
+        ..Default::default()
+    };
+    patch.filter_env = AdsrParams::new(1.0, 150.0, 0.0, 150.0);
+    patch.spread = 0.7;
+    patch
+}
+
+fn noise_sweep() -> Patch {
+    let mut patch = Patch::default();
+    patch.oscs[0].kind = OscKind::Noise;
+    patch.amp_env = AdsrParams::new(50.0, 2_000.0, 0.3, 1_000.0);
+    patch.filter = FilterParams {
+        cutoff: 200.0,
+        resonance: 0.8,
+        key_tracking: 1.0,
+        env_amount: 0.8,
+        ..Default::default()
+    };
+    patch.filter_env = AdsrParams::new(1_500.0, 1_500.0, 0.2, 1_000.0);
+    patch
+}
+
+fn shape_morph() -> Patch {
+    let mut patch = Patch::default();
+    patch.oscs[0].kind = OscKind::Wavetable;
+    patch.oscs[0].wavetable = &BASIC_SHAPES;
+    patch.oscs[0].morph = 0.5;
+    patch.oscs[0].interpolation = Interpolation::Cubic;
+    patch.amp_env = AdsrParams::new(10.0, 600.0, 0.6, 400.0);
+    patch.filter = FilterParams {
+        cutoff: 3_000.0,
+        key_tracking: 0.5,
+        ..Default::default()
+    };
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        assert_eq!(find(0, 0).unwrap().name, "Init");
+        assert_eq!(find(0, 1).unwrap().name, "Saw Lead");
+        assert!(find(0, FACTORY_BANK.len() as u8).is_none());
+        assert!(find(1, 0).is_none());
+    }
+
+    #[test]
+    fn presets_differ_from_init() {
+        for preset in &FACTORY_BANK[1..] {
+            assert_ne!((preset.patch)(), Patch::default(), "{}", preset.name);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use micromath::F32Ext;
+
+use crate::SAMPLE_RATE;
+
+/// One-pole smoothing of a control value, removes steps of 7-bit MIDI values
+#[derive(Clone, Copy, Debug)]
+pub struct Smoother {
+    value: f32,
+    target: f32,
+    coef: f32,
+}
+
+impl Smoother {
+    /// Smoother reaching ~63% of the target in `time_ms` when advanced every `period` samples
+    pub fn new(time_ms: f32, period: usize) -> Self {
+        Self {
+            value: 0.0,
+            target: 0.0,
+            coef: 1.0 - (-1_000.0 * period as f32 / (time_ms * SAMPLE_RATE as f32)).exp(),
+        }
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Jump to value immediately
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Move towards the target by one update
+    pub fn advance(&mut self) -> f32 {
+        self.value += (self.target - self.value) * self.coef;
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reaches_target() {
+        let mut smoother = Smoother::new(10.0, 32);
+        smoother.set_target(1.0);
+
+        // 63% after the time constant of 15 updates
+        for _ in 0..15 {
+            smoother.advance();
+        }
+        assert!((smoother.value() - 0.63).abs() < 0.02);
+
+        for _ in 0..200 {
+            smoother.advance();
+        }
+        assert!((smoother.value() - 1.0).abs() < 1e-4);
+    }
+}
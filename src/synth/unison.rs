@@ -0,0 +1,117 @@
+pub const MAX_UNISON: usize = 8;
+/// Total count of oscillator instances, i.e. unison copies times oscillators per copy, all active voices may run.
+/// Matches all voices playing every oscillator without unison, the load the voice count already commits to.
+pub const OSC_BUDGET: usize = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnisonParams {
+    /// Count of detuned copies of each oscillator, 1..=`MAX_UNISON`
+    pub voices: u8,
+    /// Distance between the lowest and the highest copy in cents
+    pub detune: f32,
+    /// Stereo width of copies in 0..1 range
+    pub stereo: f32,
+    /// Start copies at random phases on note-on, otherwise they keep running freely
+    pub random_phase: bool,
+}
+
+impl UnisonParams {
+    /// Position of copy `index` of `count` in -1..1 range, a single copy is centered
+    pub fn position(index: usize, count: usize) -> f32 {
+        if count <= 1 {
+            0.0
+        } else {
+            index as f32 / (count - 1) as f32 * 2.0 - 1.0
+        }
+    }
+
+    /// Pitch offset of copy `index` of `count` in semitones
+    pub fn detune_offset(&self, index: usize, count: usize) -> f32 {
+        self.detune_at(Self::position(index, count))
+    }
+
+    /// Pan offset of copy `index` of `count`
+    pub fn pan_offset(&self, index: usize, count: usize) -> f32 {
+        self.pan_at(Self::position(index, count))
+    }
+
+    /// Pitch offset in semitones of a copy at `position` in -1..1 range
+    pub fn detune_at(&self, position: f32) -> f32 {
+        position * self.detune / 2.0 / 100.0
+    }
+
+    /// Pan offset of a copy at `position` in -1..1 range
+    pub fn pan_at(&self, position: f32) -> f32 {
+        position * self.stereo
+    }
+}
+
+impl Default for UnisonParams {
+    fn default() -> Self {
+        Self {
+            voices: 1,
+            detune: 20.0,
+            stereo: 0.5,
+            random_phase: true,
+        }
+    }
+}
+
+/// Unison count allowed per voice when `active_voices` are sounding, each copy running `oscs` oscillators
+pub fn unison_limit(active_voices: usize, oscs: usize) -> usize {
+    (OSC_BUDGET / (active_voices.max(1) * oscs.max(1))).clamp(1, MAX_UNISON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detune_is_symmetric() {
+        let params = UnisonParams {
+            detune: 40.0,
+            ..Default::default()
+        };
+
+        assert_eq!(params.detune_offset(0, 1), 0.0);
+        assert!((params.detune_offset(0, 5) + 0.2).abs() < 1e-6);
+        assert!((params.detune_offset(4, 5) - 0.2).abs() < 1e-6);
+        assert!(params.detune_offset(2, 5).abs() < 1e-6);
+
+        let sum: f32 = (0..4).map(|index| params.detune_offset(index, 4)).sum();
+        assert!(sum.abs() < 1e-6);
+    }
+
+    #[test]
+    fn pan_is_balanced() {
+        let params = UnisonParams {
+            stereo: 1.0,
+            ..Default::default()
+        };
+
+        for count in 1..=MAX_UNISON {
+            let sum: f32 = (0..count)
+                .map(|index| params.pan_offset(index, count))
+                .sum();
+            assert!(sum.abs() < 1e-6, "{} copies", count);
+            for index in 0..count {
+                assert!(params.pan_offset(index, count).abs() <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn budget_limits_unison() {
+        assert_eq!(unison_limit(0, 1), MAX_UNISON);
+        assert_eq!(unison_limit(4, 1), MAX_UNISON);
+        assert_eq!(unison_limit(8, 1), 6);
+        assert_eq!(unison_limit(16, 1), 3);
+        assert_eq!(unison_limit(64, 1), 1);
+
+        // Every enabled oscillator of a copy counts
+        assert_eq!(unison_limit(4, 3), 4);
+        assert_eq!(unison_limit(8, 3), 2);
+        assert_eq!(unison_limit(16, 3), 1);
+        assert_eq!(unison_limit(1, 0), MAX_UNISON);
+    }
+}
@@ -0,0 +1,88 @@
+use micromath::F32Ext;
+
+pub const MAX_VELOCITY: u8 = 127;
+
+/// Dynamic range of exponential curve, velocity 1 is this much quieter than velocity 127
+const EXP_RANGE_DB: f32 = 40.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    /// Linear in decibels, feels more natural for percussive playing
+    Exponential,
+    /// Ignore velocity, every note plays at full level
+    Fixed,
+}
+
+impl VelocityCurve {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VelocityCurve::Linear => "Linear",
+            VelocityCurve::Exponential => "Exp",
+            VelocityCurve::Fixed => "Fixed",
+        }
+    }
+
+    /// Convert MIDI velocity to gain in 0..=1 range
+    pub fn gain(&self, velocity: u8) -> f32 {
+        let velocity = velocity.min(MAX_VELOCITY);
+
+        match self {
+            VelocityCurve::Linear => velocity as f32 / MAX_VELOCITY as f32,
+            VelocityCurve::Exponential => {
+                if velocity == 0 {
+                    0.0
+                } else {
+                    let db = (velocity as f32 / MAX_VELOCITY as f32 - 1.0) * EXP_RANGE_DB;
+                    10f32.powf(db / 20.0)
+                }
+            }
+            VelocityCurve::Fixed => 1.0,
+        }
+    }
+}
+
+impl core::fmt::Display for VelocityCurve {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_velocity_is_unity_gain() {
+        for curve in [
+            VelocityCurve::Linear,
+            VelocityCurve::Exponential,
+            VelocityCurve::Fixed,
+        ] {
+            assert!((curve.gain(MAX_VELOCITY) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn curves_are_monotonic() {
+        for curve in [VelocityCurve::Linear, VelocityCurve::Exponential] {
+            for velocity in 1..=MAX_VELOCITY {
+                assert!(curve.gain(velocity) > curve.gain(velocity - 1));
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_range() {
+        let gain = VelocityCurve::Exponential.gain(1);
+        assert!(gain > 0.009 && gain < 0.011);
+        assert!(VelocityCurve::Exponential.gain(64) < VelocityCurve::Linear.gain(64));
+    }
+
+    #[test]
+    fn fixed_ignores_velocity() {
+        assert_eq!(VelocityCurve::Fixed.gain(1), 1.0);
+        assert_eq!(VelocityCurve::Fixed.gain(64), 1.0);
+    }
+}
@@ -0,0 +1,561 @@
+use core::f32::consts::FRAC_PI_4;
+
+use micromath::F32Ext;
+
+use super::{
+    envelope::Adsr,
+    filter::Svf,
+    glide::Glide,
+    lfo::{Lfo, DEFAULT_TEMPO},
+    mod_matrix::ModSources,
+    osc::OscState,
+    patch::{Patch, OSC_COUNT},
+    smooth::Smoother,
+    unison::{UnisonParams, MAX_UNISON},
+    voice_alloc::VoiceSlot,
+};
+use crate::{
+    midi::note::{pitch_freq, Note},
+    SAMPLE_RATE,
+};
+
+/// Count of samples between control-rate updates, i.e. oscillator pitch recalculation
+pub const CONTROL_PERIOD: usize = 32;
+
+/// Fixed voice gain leaving some headroom for polyphony
+const VOICE_GAIN: f32 = 0.2;
+/// Time constant of channel and polyphonic aftertouch smoothing in ms
+pub const AFTERTOUCH_SMOOTHING_MS: f32 = 10.0;
+/// Fade out time of unison copies dropped by `Voice::limit_unison` in ms
+const UNISON_FADE_MS: f32 = 5.0;
+
+/// Modulation values shared by all voices, updated by `Synth` at control rate
+#[derive(Clone, Copy, Debug)]
+pub struct GlobalMod {
+    pub tempo: f32,
+    pub lfo: f32,
+    /// Mod wheel in 0..1 range
+    pub mod_wheel: f32,
+    /// Smoothed channel aftertouch in 0..1 range
+    pub aftertouch: f32,
+    /// Smoothed pitch bend in semitones
+    pub bend: f32,
+    /// Master tuning offset in semitones
+    pub tuning: f32,
+}
+
+impl Default for GlobalMod {
+    fn default() -> Self {
+        Self {
+            tempo: DEFAULT_TEMPO,
+            lfo: 0.0,
+            mod_wheel: 0.0,
+            aftertouch: 0.0,
+            bend: 0.0,
+            tuning: 0.0,
+        }
+    }
+}
+
+pub struct Voice {
+    /// Oscillators of each unison copy
+    oscs: [[OscState; OSC_COUNT]; MAX_UNISON],
+    /// Count of unison copies, set at note-on and reduced when polyphony rises
+    unison: usize,
+    /// Copies above `unison` dropped by `limit_unison`, still rendered while they fade out
+    fading: usize,
+    /// Gain of fading copies, falling from 1.0 to 0.0 over `UNISON_FADE_MS`
+    fade: f32,
+    note: Option<Note>,
+    /// Current pitch, moving towards the note with portamento
+    glide: Glide,
+    velocity: f32,
+    /// Polyphonic aftertouch of the note in 0..1 range
+    pressure: Smoother,
+    /// Key of the note is down, the voice may also be held by pedals after key is released
+    key_down: bool,
+    /// Key was released while sustain pedal was down
+    sustained: bool,
+    /// Key was down when sostenuto pedal was pressed
+    sostenuto: bool,
+    amp_env: Adsr,
+    filter_env: Adsr,
+    /// Left and right filters, the right one is only used for stereo unison
+    filters: [Svf; 2],
+    lfo: Lfo,
+    /// Oscillator levels with modulation applied
+    levels: [f32; OSC_COUNT],
+    /// Position in stereo field assigned by spread, -1..1 range
+    spread: f32,
+    /// Left and right gains for modulated pan
+    pan_gains: (f32, f32),
+    /// Left and right gains of each unison copy ramped per sample, used only for stereo unison
+    unison_gains: [(f32, f32); MAX_UNISON],
+    unison_gain_steps: [(f32, f32); MAX_UNISON],
+    stereo_unison: bool,
+    /// Tremolo gain ramped per sample towards the control-rate value
+    amp_mod: f32,
+    amp_mod_step: f32,
+    control_counter: usize,
+}
+
+impl Voice {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            oscs: core::array::from_fn(|copy| {
+                core::array::from_fn(|index| {
+                    OscState::new(seed ^ ((index as u32 + 1) << 16) ^ ((copy as u32) << 24))
+                })
+            }),
+            unison: 1,
+            fading: 0,
+            fade: 0.0,
+            note: None,
+            glide: Glide::default(),
+            velocity: 1.0,
+            pressure: Smoother::new(AFTERTOUCH_SMOOTHING_MS, CONTROL_PERIOD),
+            key_down: false,
+            sustained: false,
+            sostenuto: false,
+            amp_env: Adsr::new(),
+            filter_env: Adsr::new(),
+            filters: [Svf::new(); 2],
+            lfo: Lfo::new(seed),
+            levels: [0.0; OSC_COUNT],
+            spread: 0.0,
+            pan_gains: pan_gains(0.0),
+            unison_gains: [pan_gains(0.0); MAX_UNISON],
+            unison_gain_steps: [(0.0, 0.0); MAX_UNISON],
+            stereo_unison: false,
+            amp_mod: 1.0,
+            amp_mod_step: 0.0,
+            control_counter: 0,
+        }
+    }
+
+    /// Start playing note, `velocity` is a gain already mapped by `VelocityCurve`.
+    /// Unison count of the patch is reduced to `unison_limit` to stay within CPU budget.
+    /// With `glide` pitch slides from the note the voice still plays, meant for mono modes.
+    pub fn note_on(
+        &mut self,
+        note: Note,
+        velocity: f32,
+        patch: &Patch,
+        unison_limit: usize,
+        glide: bool,
+    ) {
+        if glide && self.is_active() {
+            self.glide
+                .glide_to(note.pitch(), &patch.glide, CONTROL_PERIOD);
+        } else {
+            self.glide.set(note.pitch());
+        }
+
+        if !self.is_active() {
+            self.filters.iter_mut().for_each(Svf::reset);
+            self.amp_mod = 1.0;
+            self.stereo_unison = false;
+        }
+
+        self.unison = (patch.unison.voices as usize).clamp(1, unison_limit.clamp(1, MAX_UNISON));
+        self.fading = 0;
+        if patch.unison.random_phase && self.unison > 1 {
+            self.oscs
+                .iter_mut()
+                .flatten()
+                .for_each(OscState::randomize_phase);
+        }
+
+        self.lfo.trigger(&patch.voice_lfo);
+
+        self.note = Some(note);
+        self.velocity = velocity;
+        self.pressure.reset(0.0);
+        self.key_down = true;
+        self.sustained = false;
+        self.sostenuto = false;
+        self.amp_env.gate_on();
+        self.filter_env.gate_on();
+        // Update pitch on next sample
+        self.control_counter = 0;
+    }
+
+    /// Change the note of a held voice without retriggering envelopes, gliding if portamento is enabled
+    pub fn legato(&mut self, note: Note, patch: &Patch) {
+        self.note = Some(note);
+        self.glide
+            .glide_to(note.pitch(), &patch.glide, CONTROL_PERIOD);
+    }
+
+    /// Release the note regardless of pedals, the voice keeps sounding until amplitude envelope release stage finishes
+    pub fn note_off(&mut self) {
+        self.key_down = false;
+        self.sustained = false;
+        self.sostenuto = false;
+        self.amp_env.gate_off();
+        self.filter_env.gate_off();
+    }
+
+    /// Silence the voice immediately
+    pub fn kill(&mut self) {
+        self.note_off();
+        self.note = None;
+        self.amp_env.reset();
+        self.filter_env.reset();
+    }
+
+    /// Key of the note is released, the note keeps being held if `sustain` pedal is down or it is latched by sostenuto
+    pub fn release_key(&mut self, sustain: bool) {
+        self.key_down = false;
+        self.sustained = sustain;
+        self.update_gate();
+    }
+
+    /// Sostenuto pedal pressed, hold the note until the pedal is released if its key is down
+    pub fn latch_sostenuto(&mut self) {
+        self.sostenuto = self.is_key_down();
+    }
+
+    pub fn release_sustain(&mut self) {
+        self.sustained = false;
+        self.update_gate();
+    }
+
+    pub fn release_sostenuto(&mut self) {
+        self.sostenuto = false;
+        self.update_gate();
+    }
+
+    fn update_gate(&mut self) {
+        if self.is_held() && !self.key_down && !self.sustained && !self.sostenuto {
+            self.amp_env.gate_off();
+            self.filter_env.gate_off();
+        }
+    }
+
+    /// Set polyphonic aftertouch in 0..1 range, change is smoothed
+    pub fn set_pressure(&mut self, pressure: f32) {
+        self.pressure.set_target(pressure.clamp(0.0, 1.0));
+    }
+
+    /// Set stereo position used when patch spread is enabled
+    pub fn set_spread(&mut self, spread: f32) {
+        self.spread = spread.clamp(-1.0, 1.0);
+    }
+
+    pub fn current_note(&self) -> Option<Note> {
+        self.note
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.note.is_some()
+    }
+
+    /// The note is held, i.e. the voice is active and not in release stage
+    pub fn is_held(&self) -> bool {
+        self.is_active() && !self.amp_env.is_released()
+    }
+
+    /// The note is held by its key rather than only by pedals
+    pub fn is_key_down(&self) -> bool {
+        self.is_held() && self.key_down
+    }
+
+    /// Count of unison copies running, not counting dropped ones still fading out
+    pub fn unison(&self) -> usize {
+        self.unison
+    }
+
+    /// Drop unison copies above `limit`, so that voices started later fit into CPU budget.
+    /// Dropped copies fade out rather than stopping mid-waveform, those dropped
+    /// while others still fade join them at their current gain.
+    pub fn limit_unison(&mut self, limit: usize) {
+        let limit = limit.max(1);
+        if limit < self.unison {
+            if self.fading == 0 {
+                self.fade = 1.0;
+            }
+            self.fading += self.unison - limit;
+            self.unison = limit;
+        }
+    }
+
+    /// Unison copies are summed with random phases, so their level grows as square root of their summed power
+    fn unison_gain(&self) -> f32 {
+        let power = self.unison as f32 + self.fading as f32 * self.fade * self.fade;
+        if power > 1.0 {
+            1.0 / power.sqrt()
+        } else {
+            1.0
+        }
+    }
+
+    fn update_control(&mut self, patch: &Patch, global: &GlobalMod) {
+        let pitch = self.glide.advance();
+        let pressure = self.pressure.advance();
+
+        // Sources are sampled before LFO advances, so that LFO rate can be modulated
+        let mods = patch.mod_matrix.eval(&ModSources {
+            amp_env: self.amp_env.level(),
+            filter_env: self.filter_env.level(),
+            voice_lfo: self.lfo.value(),
+            global_lfo: global.lfo,
+            velocity: self.velocity,
+            pitch,
+            mod_wheel: global.mod_wheel,
+            aftertouch: global.aftertouch,
+            poly_aftertouch: pressure,
+        });
+
+        let voice_lfo = self.lfo.advance(
+            &patch.voice_lfo,
+            global.tempo,
+            mods.lfo_rate,
+            CONTROL_PERIOD,
+        );
+        let global_lfo = global.lfo;
+
+        let vibrato = voice_lfo * (patch.voice_lfo.pitch_depth + mods.vibrato)
+            + global_lfo * patch.global_lfo.pitch_depth;
+
+        // Remaining copies move to their new places as the dropped ones fade out
+        let copies = self.unison + self.fading;
+        let mut positions = [0.0; MAX_UNISON];
+        for (copy, position) in positions.iter_mut().enumerate().take(copies) {
+            let target = UnisonParams::position(copy, self.unison);
+            *position = target + (UnisonParams::position(copy, copies) - target) * self.fade;
+        }
+
+        // Frequency ratios of unison copies
+        let mut ratios = [1.0; MAX_UNISON];
+        if copies > 1 {
+            for (ratio, position) in ratios.iter_mut().zip(positions).take(copies) {
+                *ratio = 2f32.powf(patch.unison.detune_at(position) / 12.0);
+            }
+        }
+
+        for (index, osc) in patch.oscs.iter().enumerate() {
+            let freq = pitch_freq(
+                pitch + global.bend + global.tuning + vibrato + osc.detune() + mods.pitch[index],
+            );
+            for (copy, ratio) in ratios.iter().enumerate().take(copies) {
+                self.oscs[copy][index].set_freq(freq * ratio);
+            }
+            self.levels[index] = (osc.level + mods.level[index]).clamp(0.0, 1.0);
+        }
+
+        let cutoff_mod = voice_lfo * patch.voice_lfo.cutoff_depth
+            + global_lfo * patch.global_lfo.cutoff_depth
+            + mods.cutoff;
+        let cutoff = patch.filter.modulated_cutoff(
+            pitch,
+            self.filter_env.level(),
+            self.velocity,
+            cutoff_mod,
+        );
+        for filter in self.filters.iter_mut() {
+            filter.set(cutoff, patch.filter.resonance + mods.resonance);
+        }
+
+        let pan = patch.pan + self.spread * patch.spread + mods.pan;
+        self.pan_gains = pan_gains(pan);
+        // Stays stereo once copies drop to one, switching filters mid-note would click
+        self.stereo_unison = patch.unison.stereo > 0.0 && (copies > 1 || self.stereo_unison);
+        if self.stereo_unison {
+            for ((gains, steps), position) in self
+                .unison_gains
+                .iter()
+                .zip(self.unison_gain_steps.iter_mut())
+                .zip(positions)
+                .take(copies)
+            {
+                let target = pan_gains(pan + patch.unison.pan_at(position));
+                *steps = (
+                    (target.0 - gains.0) / CONTROL_PERIOD as f32,
+                    (target.1 - gains.1) / CONTROL_PERIOD as f32,
+                );
+            }
+        }
+
+        // Tremolo only attenuates, LFO at its lowest point keeps the full level
+        let tremolo = (1.0 - patch.voice_lfo.amp_depth * (0.5 + 0.5 * voice_lfo))
+            * (1.0 - patch.global_lfo.amp_depth * (0.5 + 0.5 * global_lfo))
+            * (1.0 + mods.volume).clamp(0.0, 2.0);
+        self.amp_mod_step = (tremolo - self.amp_mod) / CONTROL_PERIOD as f32;
+    }
+
+    /// Next stereo frame, `None` if the voice is idle
+    pub fn next_sample(&mut self, patch: &Patch, global: &GlobalMod) -> Option<(f32, f32)> {
+        if !self.is_active() {
+            return None;
+        }
+
+        if self.control_counter == 0 {
+            self.update_control(patch, global);
+        }
+        self.control_counter = (self.control_counter + 1) % CONTROL_PERIOD;
+
+        let level = self.amp_env.next_sample(&patch.amp_env);
+        self.filter_env.next_sample(&patch.filter_env);
+        if !self.amp_env.is_active() {
+            self.note = None;
+            self.filter_env.reset();
+        }
+
+        self.amp_mod += self.amp_mod_step;
+
+        if self.fading > 0 {
+            self.fade -= 1_000.0 / (UNISON_FADE_MS * SAMPLE_RATE as f32);
+            if self.fade <= 0.0 {
+                self.fading = 0;
+                self.fade = 0.0;
+            }
+        }
+
+        let gain = VOICE_GAIN * self.velocity * level * self.amp_mod * self.unison_gain();
+        let (unison, fade) = (self.unison, self.fade);
+        let copy_gain = |copy: usize| if copy < unison { 1.0 } else { fade };
+
+        if self.stereo_unison {
+            let (mut left, mut right) = (0.0, 0.0);
+            for (copy, ((oscs, gains), steps)) in self
+                .oscs
+                .iter_mut()
+                .zip(self.unison_gains.iter_mut())
+                .zip(self.unison_gain_steps.iter())
+                .take(unison + self.fading)
+                .enumerate()
+            {
+                gains.0 += steps.0;
+                gains.1 += steps.1;
+                let sample = oscs_sample(oscs, patch, &self.levels) * copy_gain(copy);
+                left += sample * gains.0;
+                right += sample * gains.1;
+            }
+
+            let left = self.filters[0].process(left, patch.filter.mode);
+            let right = self.filters[1].process(right, patch.filter.mode);
+
+            Some((left * gain, right * gain))
+        } else {
+            let sample: f32 = self
+                .oscs
+                .iter_mut()
+                .take(unison + self.fading)
+                .enumerate()
+                .map(|(copy, oscs)| oscs_sample(oscs, patch, &self.levels) * copy_gain(copy))
+                .sum();
+
+            let sample = self.filters[0].process(sample, patch.filter.mode) * gain;
+
+            Some((sample * self.pan_gains.0, sample * self.pan_gains.1))
+        }
+    }
+}
+
+/// Sum of a single unison copy of oscillators
+fn oscs_sample(oscs: &mut [OscState; OSC_COUNT], patch: &Patch, levels: &[f32; OSC_COUNT]) -> f32 {
+    oscs.iter_mut()
+        .zip(patch.oscs.iter())
+        .zip(levels.iter())
+        .filter(|(_, level)| **level > 0.0)
+        .map(|((state, osc), level)| state.next_sample(osc) * level)
+        .sum()
+}
+
+/// Constant-power pan law for pan position in -1..1 range
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+impl VoiceSlot for Voice {
+    fn slot_note(&self) -> Option<Note> {
+        self.note
+    }
+
+    /// Voices held only by pedals are stolen before those with the key down
+    fn slot_held(&self) -> bool {
+        self.is_key_down()
+    }
+
+    fn slot_level(&self) -> f32 {
+        self.amp_env.level()
+    }
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_law_is_constant_power() {
+        for i in -10..=10 {
+            let (left, right) = pan_gains(i as f32 / 10.0);
+            assert!((left * left + right * right - 1.0).abs() < 1e-3);
+        }
+
+        let (left, right) = pan_gains(-1.0);
+        assert!(left > 0.999 && right < 1e-3);
+
+        let (left, right) = pan_gains(0.0);
+        assert!((left - right).abs() < 1e-4);
+    }
+
+    #[test]
+    fn glide_only_when_requested() {
+        let mut patch = Patch::default();
+        patch.glide.time = 100.0;
+        let mut voice = Voice::new(1);
+
+        // Releasing voice reused by another note starts at the new pitch
+        voice.note_on(Note::C2, 1.0, &patch, 1, false);
+        voice.note_off();
+        voice.note_on(Note::C6, 1.0, &patch, 1, false);
+        assert!(!voice.glide.is_gliding());
+        assert_eq!(voice.glide.pitch(), Note::C6.pitch());
+
+        voice.note_on(Note::C2, 1.0, &patch, 1, true);
+        assert!(voice.glide.is_gliding());
+    }
+
+    #[test]
+    fn dropped_unison_copies_fade_out() {
+        let mut patch = Patch::default();
+        patch.unison.voices = 8;
+        let global = GlobalMod::default();
+        let mut voice = Voice::new(1);
+        voice.note_on(Note::A4, 1.0, &patch, MAX_UNISON, false);
+
+        // Largest second difference, a sine bends smoothly while a click breaks its slope
+        let max_bend = |voice: &mut Voice, samples: usize| {
+            let mut history = [0.0; 2];
+            (0..samples).fold(0.0, |max_bend: f32, n| {
+                let sample = voice.next_sample(&patch, &global).unwrap().0;
+                let bend = (sample - 2.0 * history[1] + history[0]).abs();
+                history = [history[1], sample];
+                if n >= 2 {
+                    max_bend.max(bend)
+                } else {
+                    max_bend
+                }
+            })
+        };
+
+        let before = max_bend(&mut voice, 2_000);
+        voice.limit_unison(1);
+        assert_eq!(voice.unison(), 1);
+
+        let dropping = max_bend(&mut voice, 500);
+        let after = max_bend(&mut voice, 2_000);
+        let steady = before.max(after);
+        assert!(dropping < steady * 1.5, "{} > {}", dropping, steady);
+        assert_eq!(voice.fading, 0);
+    }
+}
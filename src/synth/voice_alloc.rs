@@ -0,0 +1,342 @@
+use core::cmp::Reverse;
+
+use crate::midi::note::Note;
+
+/// What to do when a note is played while all voices are busy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum StealPolicy {
+    /// Steal the voice that started playing first
+    #[default]
+    Oldest,
+    /// Steal the voice with the lowest envelope level
+    Quietest,
+    /// Low notes have priority, steal the voice playing the highest note
+    LowestPriority,
+    /// High notes have priority, steal the voice playing the lowest note
+    HighestPriority,
+}
+
+impl StealPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StealPolicy::Oldest => "Oldest",
+            StealPolicy::Quietest => "Quietest",
+            StealPolicy::LowestPriority => "Low",
+            StealPolicy::HighestPriority => "High",
+        }
+    }
+}
+
+impl core::fmt::Display for StealPolicy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+/// Voice state the allocator decides on
+pub trait VoiceSlot {
+    /// Note played by voice, `None` if voice is free
+    fn slot_note(&self) -> Option<Note>;
+
+    /// Note is still held, i.e. voice is not in release stage
+    fn slot_held(&self) -> bool;
+
+    /// Current amplitude level
+    fn slot_level(&self) -> f32;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Allocation {
+    /// Voice was free
+    Free(usize),
+    /// Voice already plays the same note
+    Retrigger(usize),
+    /// Voice was in release stage
+    Released(usize),
+    /// Voice holding another note was stolen
+    Stolen(usize),
+}
+
+impl Allocation {
+    pub fn index(&self) -> usize {
+        match *self {
+            Allocation::Free(index)
+            | Allocation::Retrigger(index)
+            | Allocation::Released(index)
+            | Allocation::Stolen(index) => index,
+        }
+    }
+}
+
+/// Picks a voice for a new note. Free voices are preferred, then voices in release stage, only then a held voice is stolen according to `StealPolicy`.
+pub struct VoiceAllocator<const N: usize> {
+    policy: StealPolicy,
+    retrigger_same_note: bool,
+    stamps: [u32; N],
+    counter: u32,
+}
+
+impl<const N: usize> VoiceAllocator<N> {
+    pub fn new(policy: StealPolicy) -> Self {
+        Self {
+            policy,
+            retrigger_same_note: true,
+            stamps: [0; N],
+            counter: 0,
+        }
+    }
+
+    pub fn policy(&self) -> StealPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: StealPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn retrigger_same_note(&self) -> bool {
+        self.retrigger_same_note
+    }
+
+    /// Reuse the voice already playing the same note instead of allocating another one
+    pub fn set_retrigger_same_note(&mut self, retrigger: bool) {
+        self.retrigger_same_note = retrigger;
+    }
+
+    /// Count of allocations made since the voice was allocated last time
+    pub fn age(&self, index: usize) -> u32 {
+        self.counter.wrapping_sub(self.stamps[index])
+    }
+
+    pub fn allocate<V: VoiceSlot>(&mut self, note: Note, voices: &[V; N]) -> Allocation {
+        let allocation = self.find(note, voices);
+
+        self.counter = self.counter.wrapping_add(1);
+        self.stamps[allocation.index()] = self.counter;
+
+        allocation
+    }
+
+    fn oldest(&self, indices: impl Iterator<Item = usize>) -> Option<usize> {
+        indices.min_by_key(|&index| Reverse(self.age(index)))
+    }
+
+    fn find<V: VoiceSlot>(&self, note: Note, voices: &[V; N]) -> Allocation {
+        if self.retrigger_same_note {
+            if let Some(index) = voices
+                .iter()
+                .position(|voice| voice.slot_note() == Some(note))
+            {
+                return Allocation::Retrigger(index);
+            }
+        }
+
+        // Reuse the voice which is idle for longest time
+        if let Some(index) =
+            self.oldest((0..N).filter(|&index| voices[index].slot_note().is_none()))
+        {
+            return Allocation::Free(index);
+        }
+
+        if let Some(index) =
+            (0..N)
+                .filter(|&index| !voices[index].slot_held())
+                .min_by(|&lhs, &rhs| {
+                    voices[lhs]
+                        .slot_level()
+                        .total_cmp(&voices[rhs].slot_level())
+                })
+        {
+            return Allocation::Released(index);
+        }
+
+        let stolen = match self.policy {
+            StealPolicy::Oldest => self.oldest(0..N),
+            StealPolicy::Quietest => (0..N).min_by(|&lhs, &rhs| {
+                voices[lhs]
+                    .slot_level()
+                    .total_cmp(&voices[rhs].slot_level())
+            }),
+            StealPolicy::LowestPriority => {
+                (0..N).max_by_key(|&index| voices[index].slot_note().map(u8::from))
+            }
+            StealPolicy::HighestPriority => {
+                (0..N).min_by_key(|&index| voices[index].slot_note().map(u8::from))
+            }
+        };
+
+        Allocation::Stolen(stolen.unwrap_or(0))
+    }
+}
+
+impl<const N: usize> Default for VoiceAllocator<N> {
+    fn default() -> Self {
+        Self::new(StealPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Default)]
+    struct TestVoice {
+        note: Option<Note>,
+        held: bool,
+        level: f32,
+    }
+
+    impl TestVoice {
+        fn held(note: Note, level: f32) -> Self {
+            Self {
+                note: Some(note),
+                held: true,
+                level,
+            }
+        }
+    }
+
+    impl VoiceSlot for TestVoice {
+        fn slot_note(&self) -> Option<Note> {
+            self.note
+        }
+
+        fn slot_held(&self) -> bool {
+            self.held
+        }
+
+        fn slot_level(&self) -> f32 {
+            self.level
+        }
+    }
+
+    fn play<const N: usize>(
+        alloc: &mut VoiceAllocator<N>,
+        voices: &mut [TestVoice; N],
+        note: Note,
+    ) -> Allocation {
+        let allocation = alloc.allocate(note, voices);
+        voices[allocation.index()] = TestVoice::held(note, 1.0);
+        allocation
+    }
+
+    #[test]
+    fn free_voices_first() {
+        let mut alloc = VoiceAllocator::<4>::default();
+        let mut voices = [TestVoice::default(); 4];
+
+        let indices = [Note::C4, Note::D4, Note::E4, Note::F4]
+            .map(|note| play(&mut alloc, &mut voices, note));
+
+        assert!(indices.iter().all(|a| matches!(a, Allocation::Free(_))));
+        let mut indices = indices.map(|a| a.index());
+        indices.sort();
+        assert_eq!(indices, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn free_voice_idle_for_longest_is_reused() {
+        let mut alloc = VoiceAllocator::<2>::default();
+        let mut voices = [TestVoice::default(); 2];
+
+        let first = play(&mut alloc, &mut voices, Note::C4).index();
+        let second = play(&mut alloc, &mut voices, Note::D4).index();
+        voices[first] = TestVoice::default();
+        voices[second] = TestVoice::default();
+
+        assert_eq!(alloc.allocate(Note::E4, &voices), Allocation::Free(first));
+    }
+
+    #[test]
+    fn same_note_retrigger() {
+        let mut alloc = VoiceAllocator::<4>::default();
+        let mut voices = [TestVoice::default(); 4];
+
+        let first = play(&mut alloc, &mut voices, Note::C4).index();
+        play(&mut alloc, &mut voices, Note::D4);
+        assert_eq!(
+            alloc.allocate(Note::C4, &voices),
+            Allocation::Retrigger(first)
+        );
+
+        alloc.set_retrigger_same_note(false);
+        assert!(matches!(
+            alloc.allocate(Note::C4, &voices),
+            Allocation::Free(_)
+        ));
+    }
+
+    #[test]
+    fn released_voices_before_stealing() {
+        let mut alloc = VoiceAllocator::<3>::default();
+        let mut voices = [TestVoice::default(); 3];
+
+        for note in [Note::C4, Note::D4, Note::E4] {
+            play(&mut alloc, &mut voices, note);
+        }
+        voices[1].held = false;
+        voices[1].level = 0.3;
+        voices[2].held = false;
+        voices[2].level = 0.1;
+
+        assert_eq!(alloc.allocate(Note::F4, &voices), Allocation::Released(2));
+    }
+
+    #[test]
+    fn steal_oldest() {
+        let mut alloc = VoiceAllocator::<3>::new(StealPolicy::Oldest);
+        let mut voices = [TestVoice::default(); 3];
+
+        let first = play(&mut alloc, &mut voices, Note::C4).index();
+        let second = play(&mut alloc, &mut voices, Note::D4).index();
+        play(&mut alloc, &mut voices, Note::E4);
+
+        assert_eq!(
+            play(&mut alloc, &mut voices, Note::F4),
+            Allocation::Stolen(first)
+        );
+        assert_eq!(
+            play(&mut alloc, &mut voices, Note::G4),
+            Allocation::Stolen(second)
+        );
+    }
+
+    #[test]
+    fn steal_quietest() {
+        let mut alloc = VoiceAllocator::<3>::new(StealPolicy::Quietest);
+        let voices = [
+            TestVoice::held(Note::C4, 0.8),
+            TestVoice::held(Note::D4, 0.2),
+            TestVoice::held(Note::E4, 0.5),
+        ];
+
+        assert_eq!(alloc.allocate(Note::F4, &voices), Allocation::Stolen(1));
+    }
+
+    #[test]
+    fn steal_by_note_priority() {
+        let voices = [
+            TestVoice::held(Note::E4, 1.0),
+            TestVoice::held(Note::C2, 1.0),
+            TestVoice::held(Note::G6, 1.0),
+        ];
+
+        let mut alloc = VoiceAllocator::<3>::new(StealPolicy::LowestPriority);
+        assert_eq!(alloc.allocate(Note::F4, &voices), Allocation::Stolen(2));
+
+        alloc.set_policy(StealPolicy::HighestPriority);
+        assert_eq!(alloc.allocate(Note::F4, &voices), Allocation::Stolen(1));
+    }
+
+    #[test]
+    fn age_tracking() {
+        let mut alloc = VoiceAllocator::<2>::default();
+        let mut voices = [TestVoice::default(); 2];
+
+        let first = play(&mut alloc, &mut voices, Note::C4).index();
+        assert_eq!(alloc.age(first), 0);
+        let second = play(&mut alloc, &mut voices, Note::D4).index();
+        assert_eq!(alloc.age(first), 1);
+        assert_eq!(alloc.age(second), 0);
+    }
+}
@@ -1,5 +1,9 @@
-pub const WAVETABLE_SIZE: usize = 1024;
+use super::osc::WaveForm;
+use super::wavetable_gen::harmonics;
+pub use super::wavetable_gen::{MIPMAP_LEVELS, WAVETABLE_SIZE};
+use crate::SAMPLE_RATE;
 
+#[derive(Clone)]
 pub struct Wavetable {
     samples: [f32; WAVETABLE_SIZE],
 }
@@ -14,4 +18,384 @@ impl Wavetable {
 
         Self { samples }
     }
+
+    fn at(&self, index: usize) -> f32 {
+        self.samples[index % WAVETABLE_SIZE]
+    }
+
+    fn position(phase: f32) -> (usize, f32) {
+        let position = phase * WAVETABLE_SIZE as f32;
+        let index = position as usize;
+
+        (index % WAVETABLE_SIZE, position - index as f32)
+    }
+
+    /// Read the table at `phase` in 0..1 range with linear interpolation
+    pub fn read_linear(&self, phase: f32) -> f32 {
+        let (index, frac) = Self::position(phase);
+        let a = self.at(index);
+        let b = self.at(index + 1);
+
+        a + (b - a) * frac
+    }
+
+    /// Read the table at `phase` in 0..1 range with 4-point cubic Hermite interpolation
+    pub fn read_cubic(&self, phase: f32) -> f32 {
+        let (index, frac) = Self::position(phase);
+        let y0 = self.at(index + WAVETABLE_SIZE - 1);
+        let y1 = self.at(index);
+        let y2 = self.at(index + 1);
+        let y3 = self.at(index + 2);
+
+        let c1 = 0.5 * (y2 - y0);
+        let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+
+        ((c3 * frac + c2) * frac + c1) * frac + y1
+    }
+
+    pub fn read(&self, phase: f32, interpolation: Interpolation) -> f32 {
+        match interpolation {
+            Interpolation::Linear => self.read_linear(phase),
+            Interpolation::Cubic => self.read_cubic(phase),
+        }
+    }
+}
+
+impl core::fmt::Debug for Wavetable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Wavetable").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Wavetable {
+    fn eq(&self, other: &Self) -> bool {
+        self.samples == other.samples
+    }
+}
+
+/// Per-octave set of band-limited wavetables.
+/// Each level has harmonics above Nyquist frequency of its highest fundamental removed, so the waveform does not alias at any pitch.
+pub struct Mipmap {
+    levels: [Wavetable; MIPMAP_LEVELS],
+}
+
+impl Mipmap {
+    /// The highest fundamental frequency played from `level`, its harmonics stay below Nyquist frequency
+    pub fn max_freq(level: usize) -> f32 {
+        SAMPLE_RATE as f32 / 2.0 / harmonics(level) as f32
+    }
+
+    pub fn level_for(freq: f32) -> usize {
+        (0..MIPMAP_LEVELS)
+            .find(|&level| freq <= Self::max_freq(level))
+            .unwrap_or(MIPMAP_LEVELS - 1)
+    }
+
+    pub fn level(&self, level: usize) -> &Wavetable {
+        &self.levels[level.min(MIPMAP_LEVELS - 1)]
+    }
+
+    pub fn read(&self, phase: f32, level: usize, interpolation: Interpolation) -> f32 {
+        self.level(level).read(phase, interpolation)
+    }
+}
+
+/// Band-limited versions of waveforms with strong upper harmonics
+pub struct BandLimitedWaves {
+    saw: Mipmap,
+    square: Mipmap,
+}
+
+impl BandLimitedWaves {
+    /// Mipmap for waveform, `None` for waveforms that don't need band-limiting
+    pub fn get(&self, wave: WaveForm) -> Option<&Mipmap> {
+        match wave {
+            WaveForm::Saw => Some(&self.saw),
+            WaveForm::Square => Some(&self.square),
+            WaveForm::Sine | WaveForm::Triangle => None,
+        }
+    }
+}
+
+/// Generated by build script to stay in flash
+static BAND_LIMITED: BandLimitedWaves = include!(concat!(env!("OUT_DIR"), "/band_limited.rs"));
+
+pub fn band_limited() -> &'static BandLimitedWaves {
+    &BAND_LIMITED
+}
+
+/// Frames morphing from sine through triangle and saw to square, generated by build script
+pub static BASIC_SHAPES: [Wavetable; 4] = include!(concat!(env!("OUT_DIR"), "/basic_shapes.rs"));
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    Cubic,
+}
+
+/// Oscillator playing a sequence of wavetable frames, `morph` position in 0..1 range crossfades between neighbour frames
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WavetableOsc {
+    phase: f32,
+    phase_inc: f32,
+    morph: f32,
+    interpolation: Interpolation,
+}
+
+impl WavetableOsc {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            interpolation,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_freq(&mut self, freq: f32) {
+        self.phase_inc = (freq / SAMPLE_RATE as f32).clamp(0.0, 0.5);
+    }
+
+    pub fn set_morph(&mut self, morph: f32) {
+        self.morph = morph.clamp(0.0, 1.0);
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    pub fn set_phase(&mut self, phase: f32) {
+        let phase = phase % 1.0;
+        self.phase = if phase < 0.0 { phase + 1.0 } else { phase };
+    }
+
+    fn read(&self, frames: &[Wavetable]) -> f32 {
+        match frames.len() {
+            0 => 0.0,
+            1 => frames[0].read(self.phase, self.interpolation),
+            len => {
+                let position = self.morph * (len - 1) as f32;
+                let index = (position as usize).min(len - 2);
+                let frac = position - index as f32;
+
+                let a = frames[index].read(self.phase, self.interpolation);
+                let b = frames[index + 1].read(self.phase, self.interpolation);
+
+                a + (b - a) * frac
+            }
+        }
+    }
+
+    pub fn next_sample(&mut self, frames: &[Wavetable]) -> f32 {
+        let sample = self.read(frames);
+
+        self.phase += self.phase_inc;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::f32::consts::TAU;
+
+    use super::*;
+
+    fn sine() -> Wavetable {
+        Wavetable::gen(|phase| (phase * TAU).sin())
+    }
+
+    fn max_error(interpolation: Interpolation, freq: f32) -> f32 {
+        let table = [sine()];
+        let mut osc = WavetableOsc::new(interpolation);
+        osc.set_freq(freq);
+
+        (0..SAMPLE_RATE / 10)
+            .map(|n| {
+                let expected = (core::f64::consts::TAU * freq as f64 * n as f64
+                    / SAMPLE_RATE as f64)
+                    .sin() as f32;
+                (osc.next_sample(&table) - expected).abs()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn linear_matches_sine() {
+        for freq in [55.0, 440.0, 1234.5, 8000.0] {
+            let error = max_error(Interpolation::Linear, freq);
+            assert!(error < 1e-3, "{}Hz error {}", freq, error);
+        }
+    }
+
+    #[test]
+    fn cubic_matches_sine() {
+        for freq in [55.0, 440.0, 1234.5, 8000.0] {
+            let error = max_error(Interpolation::Cubic, freq);
+            assert!(error < 1e-3, "{}Hz error {}", freq, error);
+        }
+    }
+
+    #[test]
+    fn cubic_is_more_precise_between_samples() {
+        let table = sine();
+        let (linear, cubic) = (0..WAVETABLE_SIZE)
+            .map(|i| {
+                let phase = (i as f32 + 0.5) / WAVETABLE_SIZE as f32;
+                let expected = (phase * TAU).sin();
+                (
+                    (table.read_linear(phase) - expected).abs(),
+                    (table.read_cubic(phase) - expected).abs(),
+                )
+            })
+            .fold((0.0f32, 0.0f32), |(l, c), (el, ec)| (l.max(el), c.max(ec)));
+
+        assert!(cubic < linear);
+    }
+
+    #[test]
+    fn morph_crossfades_frames() {
+        let frames = [sine(), Wavetable::gen(|_| 0.0), sine()];
+        let mut osc = WavetableOsc::default();
+        osc.set_phase(0.25);
+
+        osc.set_morph(0.0);
+        assert!((osc.read(&frames) - 1.0).abs() < 1e-6);
+        osc.set_morph(0.25);
+        assert!((osc.read(&frames) - 0.5).abs() < 1e-6);
+        osc.set_morph(0.5);
+        assert!(osc.read(&frames).abs() < 1e-6);
+        osc.set_morph(1.0);
+        assert!((osc.read(&frames) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_frames_is_silent() {
+        let mut osc = WavetableOsc::default();
+        osc.set_freq(440.0);
+        assert_eq!(osc.next_sample(&[]), 0.0);
+    }
+
+    /// Energy of spectrum outside of the harmonics of `freq` relative to the total energy
+    fn aliasing_ratio(samples: &[f32], freq: f32) -> f64 {
+        let len = samples.len();
+        let bin_width = SAMPLE_RATE as f64 / len as f64;
+
+        let windowed = samples
+            .iter()
+            .enumerate()
+            .map(|(n, &s)| {
+                let hann = 0.5 - 0.5 * (core::f64::consts::TAU * n as f64 / len as f64).cos();
+                s as f64 * hann
+            })
+            .collect::<Vec<_>>();
+
+        let (mut total, mut aliased) = (0.0, 0.0);
+        for bin in 1..len / 2 {
+            let (re, im) = windowed
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, s)| {
+                    let angle = core::f64::consts::TAU * (bin * n % len) as f64 / len as f64;
+                    (re + s * angle.cos(), im - s * angle.sin())
+                });
+            let energy = re * re + im * im;
+
+            let bin_freq = bin as f64 * bin_width;
+            let harmonic = (bin_freq / freq as f64).round();
+            let is_harmonic =
+                harmonic >= 1.0 && (bin_freq - harmonic * freq as f64).abs() <= 3.0 * bin_width;
+
+            total += energy;
+            if !is_harmonic {
+                aliased += energy;
+            }
+        }
+
+        aliased / total
+    }
+
+    fn render(freq: f32, len: usize, f: impl Fn(f32) -> f32) -> Vec<f32> {
+        (0..len)
+            .map(|n| {
+                let phase = (freq as f64 * n as f64 / SAMPLE_RATE as f64).fract();
+                f(phase as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mipmap_levels() {
+        assert_eq!(Mipmap::level_for(8.0), 0);
+        assert_eq!(Mipmap::level_for(40.0), 0);
+        assert_eq!(Mipmap::level_for(48.0), 1);
+        assert_eq!(Mipmap::level_for(440.0), 4);
+        assert_eq!(Mipmap::level_for(25_000.0), MIPMAP_LEVELS - 1);
+    }
+
+    #[test]
+    fn basic_shapes_start_with_sine() {
+        let error = (0..WAVETABLE_SIZE)
+            .map(|i| {
+                let phase = i as f32 / WAVETABLE_SIZE as f32;
+                (BASIC_SHAPES[0].read_linear(phase) - (phase * TAU).sin()).abs()
+            })
+            .fold(0.0, f32::max);
+        assert!(error < 1e-5, "Sine frame error {}", error);
+
+        let square = BASIC_SHAPES.last().unwrap();
+        assert!(square.read_linear(0.25) > 0.9 && square.read_linear(0.75) < -0.9);
+    }
+
+    #[test]
+    fn band_limited_saw_shape() {
+        let saw = band_limited().get(WaveForm::Saw).unwrap();
+
+        // Lowest level has enough harmonics to be close to naive saw away from the discontinuity
+        for phase in [0.1, 0.25, 0.5, 0.75, 0.9] {
+            let expected = WaveForm::Saw.sample(phase);
+            let sample = saw.read(phase, 0, Interpolation::Linear);
+            assert!(
+                (sample - expected).abs() < 0.01,
+                "{} != {}",
+                sample,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn high_saw_does_not_alias() {
+        let saw = band_limited().get(WaveForm::Saw).unwrap();
+
+        // G8
+        let freq = 6271.93;
+        let level = Mipmap::level_for(freq);
+
+        let naive = aliasing_ratio(
+            &render(freq, 2048, |phase| WaveForm::Saw.sample(phase)),
+            freq,
+        );
+        let band_limited = aliasing_ratio(
+            &render(freq, 2048, |phase| {
+                saw.read(phase, level, Interpolation::Cubic)
+            }),
+            freq,
+        );
+
+        assert!(naive > 1e-2, "Naive saw aliasing {}", naive);
+        assert!(
+            band_limited < 1e-3,
+            "Band-limited saw aliasing {}",
+            band_limited
+        );
+    }
 }
@@ -0,0 +1,9 @@
+/// Layout of the band-limited tables, shared with the build script generating them
+pub const WAVETABLE_SIZE: usize = 1024;
+/// Count of mipmap levels, one per octave, the highest one keeps only the fundamental
+pub const MIPMAP_LEVELS: usize = 10;
+
+/// Count of harmonics kept in mipmap `level`, the lowest level keeps all the table can hold and each next one half of them
+pub fn harmonics(level: usize) -> usize {
+    ((WAVETABLE_SIZE / 2) >> level).clamp(1, WAVETABLE_SIZE / 2 - 1)
+}
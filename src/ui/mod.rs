@@ -2,6 +2,7 @@ use embedded_ui::event::CommonEvent;
 
 pub mod fps;
 pub mod logo;
+pub mod settings;
 
 #[derive(Clone)]
 pub enum Message {
@@ -0,0 +1,199 @@
+use alloc::{format, string::String};
+
+use crate::{
+    settings::Settings,
+    synth::{cc_map::SynthParam, preset::PresetChange},
+};
+
+/// Entry of the settings page
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Setting {
+    /// What happens to sounding voices on program change
+    PresetChange,
+    /// MIDI learn of the controller bound to parameter
+    Learn(SynthParam),
+    /// Store settings in flash, the only time they are written
+    Save,
+}
+
+impl Setting {
+    pub const ALL: [Self; SynthParam::ALL.len() + 2] = {
+        let mut all = [Self::PresetChange; SynthParam::ALL.len() + 2];
+        let mut index = 0;
+        while index < SynthParam::ALL.len() {
+            all[index + 1] = Self::Learn(SynthParam::ALL[index]);
+            index += 1;
+        }
+        all[index + 1] = Self::Save;
+        all
+    };
+}
+
+/// Change requested by adjusting a setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum SettingAction {
+    SetPresetChange(PresetChange),
+    StartLearn(SynthParam),
+    CancelLearn,
+    Unbind(SynthParam),
+    Save,
+}
+
+/// Settings selected by red encoder and adjusted by green one
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SettingsPage {
+    index: usize,
+}
+
+impl SettingsPage {
+    pub fn setting(&self) -> Setting {
+        Setting::ALL[self.index]
+    }
+
+    /// Move selection by encoder `offset`, wrapping around
+    pub fn scroll(&mut self, offset: i32) {
+        let len = Setting::ALL.len() as i32;
+        self.index = (self.index as i32 + offset).rem_euclid(len) as usize;
+    }
+
+    /// Action for encoder `offset` applied to the selected setting of current `settings`
+    pub fn adjust(&self, offset: i32, settings: &Settings) -> Option<SettingAction> {
+        match self.setting() {
+            Setting::PresetChange => {
+                let change = match offset.signum() {
+                    1 => PresetChange::RingOut,
+                    -1 => PresetChange::Cut,
+                    _ => return None,
+                };
+                (change != settings.preset_change).then_some(SettingAction::SetPresetChange(change))
+            }
+            Setting::Learn(param) => match (offset.signum(), settings.cc_map.learning()) {
+                (1, Some(learning)) if learning == param => None,
+                (1, _) => Some(SettingAction::StartLearn(param)),
+                (-1, Some(_)) => Some(SettingAction::CancelLearn),
+                (-1, None) => Some(SettingAction::Unbind(param)),
+                _ => None,
+            },
+            Setting::Save => (offset > 0).then_some(SettingAction::Save),
+        }
+    }
+
+    /// Selected setting with its current value, `saved` are the settings stored in flash
+    pub fn label(&self, settings: &Settings, saved: &Settings) -> String {
+        match self.setting() {
+            Setting::PresetChange => format!("Preset change: {}", settings.preset_change),
+            Setting::Learn(param) if settings.cc_map.learning() == Some(param) => {
+                format!("{}: learning...", param)
+            }
+            Setting::Learn(param) => match settings.cc_map.control(param) {
+                Some(control) => format!("{}: CC{}", param, control),
+                None => format!("{}: -", param),
+            },
+            Setting::Save if settings.to_bytes() == saved.to_bytes() => "Settings saved".into(),
+            Setting::Save => "Save settings: turn right".into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::cc_map::CcMap;
+
+    /// Page with the first parameter to learn selected
+    fn learn_page() -> SettingsPage {
+        let mut page = SettingsPage::default();
+        page.scroll(1);
+        page
+    }
+
+    #[test]
+    fn scroll_wraps() {
+        let mut page = SettingsPage::default();
+        assert_eq!(page.setting(), Setting::PresetChange);
+
+        page.scroll(-1);
+        assert_eq!(page.setting(), Setting::Save);
+        page.scroll(2);
+        assert_eq!(page.setting(), Setting::ALL[1]);
+    }
+
+    #[test]
+    fn learn_actions() {
+        let page = learn_page();
+        let cutoff = SynthParam::Cutoff;
+        let mut settings = Settings::default();
+        assert_eq!(page.setting(), Setting::Learn(cutoff));
+
+        assert_eq!(
+            page.adjust(1, &settings),
+            Some(SettingAction::StartLearn(cutoff))
+        );
+        assert_eq!(
+            page.adjust(-1, &settings),
+            Some(SettingAction::Unbind(cutoff))
+        );
+        assert_eq!(page.adjust(0, &settings), None);
+
+        settings.cc_map.start_learn(SynthParam::Pan);
+        assert_eq!(
+            page.adjust(1, &settings),
+            Some(SettingAction::StartLearn(cutoff))
+        );
+
+        settings.cc_map.start_learn(cutoff);
+        assert_eq!(page.adjust(2, &settings), None);
+        assert_eq!(page.adjust(-1, &settings), Some(SettingAction::CancelLearn));
+    }
+
+    #[test]
+    fn preset_change_actions() {
+        let page = SettingsPage::default();
+        let mut settings = Settings::default();
+        assert_eq!(page.label(&settings, &settings), "Preset change: Ring");
+
+        assert_eq!(page.adjust(1, &settings), None);
+        assert_eq!(
+            page.adjust(-1, &settings),
+            Some(SettingAction::SetPresetChange(PresetChange::Cut))
+        );
+
+        settings.preset_change = PresetChange::Cut;
+        assert_eq!(page.label(&settings, &settings), "Preset change: Cut");
+        assert_eq!(page.adjust(-3, &settings), None);
+        assert_eq!(
+            page.adjust(1, &settings),
+            Some(SettingAction::SetPresetChange(PresetChange::RingOut))
+        );
+    }
+
+    #[test]
+    fn learn_label() {
+        let page = learn_page();
+        let mut settings = Settings {
+            cc_map: CcMap::empty(),
+            ..Default::default()
+        };
+        assert_eq!(page.label(&settings, &settings), "Cutoff: -");
+
+        settings.cc_map.bind(74, SynthParam::Cutoff);
+        assert_eq!(page.label(&settings, &settings), "Cutoff: CC74");
+
+        settings.cc_map.start_learn(SynthParam::Cutoff);
+        assert_eq!(page.label(&settings, &settings), "Cutoff: learning...");
+    }
+
+    #[test]
+    fn save_action() {
+        let mut page = SettingsPage::default();
+        page.scroll(-1);
+        let saved = Settings::default();
+        let mut settings = saved.clone();
+        assert_eq!(page.label(&settings, &saved), "Settings saved");
+
+        settings.preset_change = PresetChange::Cut;
+        assert_eq!(page.label(&settings, &saved), "Save settings: turn right");
+        assert_eq!(page.adjust(1, &settings), Some(SettingAction::Save));
+        assert_eq!(page.adjust(-1, &settings), None);
+    }
+}